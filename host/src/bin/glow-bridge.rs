@@ -0,0 +1,108 @@
+// Forwards DMX from a lighting desk to the device: listens for Art-Net and
+// sACN on every interface and pushes the patched channels over the binary
+// protocol on a serial port.
+//
+//   glow-bridge PORT [UNIVERSE] [CHANNEL]
+//
+// The LEDs take 114 channels, RGB each, from CHANNEL on (default 1) in
+// UNIVERSE. Art-Net universes are the 15 bit port address and count from
+// 0, sACN's from 1, so without UNIVERSE the bridge takes the first of
+// each: Art-Net's 0 and sACN's 1. A UNIVERSE given applies to both.
+// Try it without a desk with glow-netgen, and without a board with glow-sim.
+use std::env;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::process::exit;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use glow::m6::LEDS;
+use glow_host::net::{self, Dmx, Patch, ARTNET_PORT, SACN_PORT};
+use glow_host::Client;
+use glow_proto::{Request, Response};
+
+const USAGE: &str = "usage: glow-bridge PORT [UNIVERSE] [CHANNEL]";
+// The device falls back to its pattern a second after the last frame, but
+// desks only resend unchanged universes every few seconds, so the last
+// frame is repeated meanwhile for up to HOLD
+const REFRESH: Duration = Duration::from_millis(500);
+const HOLD: Duration = Duration::from_secs(5);
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    exit(1);
+}
+
+fn listen(
+    port: u16,
+    parse: fn(&[u8]) -> Option<Dmx<'_>>,
+    patch: Patch,
+    frames: Sender<[u8; LEDS * 3]>,
+) -> UdpSocket {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))
+        .unwrap_or_else(|e| fail(&format!("can't listen on port {}: {}", port, e)));
+    let rx = socket.try_clone().unwrap();
+    thread::spawn(move || {
+        let mut buf = [0; 1024];
+        while let Ok(n) = rx.recv(&mut buf) {
+            if let Some(rgb) = parse(&buf[..n]).and_then(|dmx| patch.frame(&dmx)) {
+                if frames.send(rgb).is_err() {
+                    break;
+                }
+            }
+        }
+    });
+    socket
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let num = |n: usize, default: u16| match args.get(n).map(|s| s.parse()) {
+        None => default,
+        Some(Ok(v)) => v,
+        Some(Err(_)) => fail(USAGE),
+    };
+    let path = args.first().unwrap_or_else(|| fail(USAGE));
+    let patch = |default| {
+        Patch::new(num(1, default), num(2, 1)).unwrap_or_else(|| fail("CHANNEL is 1 to 512"))
+    };
+    let (artnet, sacn) = (patch(0), patch(1));
+    let port = serialport::new(path.as_str(), 115_200)
+        .timeout(Duration::from_millis(500))
+        .open()
+        .unwrap_or_else(|e| fail(&format!("{}: {}", path, e)));
+    let mut client = Client::new(port);
+
+    let (tx, frames) = mpsc::channel();
+    let _artnet = listen(ARTNET_PORT, net::parse_artnet, artnet, tx.clone());
+    let socket = listen(SACN_PORT, net::parse_sacn, sacn, tx);
+    // Desks multicast sACN to a group per universe
+    let [hi, lo] = sacn.universe().to_be_bytes();
+    let group = Ipv4Addr::new(239, 255, hi, lo);
+    if let Err(e) = socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED) {
+        eprintln!("warning: can't join {}: {}", group, e);
+    }
+
+    let mut last: Option<([u8; LEDS * 3], Instant)> = None;
+    loop {
+        let rgb = match frames.recv_timeout(REFRESH) {
+            // Only the newest of any that queued up while the device was
+            // busy
+            Ok(rgb) => {
+                let rgb = frames.try_iter().last().unwrap_or(rgb);
+                last = Some((rgb, Instant::now()));
+                rgb
+            }
+            Err(RecvTimeoutError::Timeout) => match last {
+                Some((rgb, at)) if at.elapsed() < HOLD => rgb,
+                _ => continue,
+            },
+            Err(RecvTimeoutError::Disconnected) => fail("listeners stopped"),
+        };
+        match client.request(&Request::PushFrame(&rgb)) {
+            Ok(Response::Ack) => {}
+            Ok(r) => eprintln!("warning: unexpected reply {:?}", r),
+            Err(e) => eprintln!("warning: {}: {}", path, e),
+        }
+    }
+}
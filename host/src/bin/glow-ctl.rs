@@ -0,0 +1,199 @@
+// Controls the device, or glow-sim, over the binary protocol on a serial
+// port.
+//
+//   glow-ctl PORT state
+//   glow-ctl PORT patterns
+//   glow-ctl PORT params
+//   glow-ctl PORT select N|NAME
+//   glow-ctl PORT set NAME VALUE
+//   glow-ctl PORT stream FILE [FPS]
+//   glow-ctl PORT diag
+//
+// `set` looks NAME up among the active pattern's parameters, then the
+// settings. `stream` reads frames in glow-replay's output format, one line
+// of hex LED colors each, from FILE or stdin when FILE is "-".
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process::exit;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use glow::m6::LEDS;
+use glow::settings::FPS;
+use glow_host::Client;
+use glow_proto::{Request, Response};
+use serialport::SerialPort;
+
+const USAGE: &str = "usage: glow-ctl PORT state|patterns|params|select N|NAME|\
+                     set NAME VALUE|stream FILE [FPS]|diag";
+
+type Port = Client<Box<dyn SerialPort>>;
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    exit(1);
+}
+
+fn request<'a>(client: &'a mut Port, req: &Request) -> Response<'a> {
+    match client.request(req) {
+        Ok(Response::Nack(status)) => fail(&format!("{:?} refused: {:?}", req, status)),
+        Ok(r) => r,
+        Err(e) => fail(&format!("{:?}: {}", req, e)),
+    }
+}
+
+// Pattern count, active pattern and its parameter and setting counts
+fn state(client: &mut Port) -> (u8, u8, u8, u8) {
+    match request(client, &Request::GetState) {
+        Response::State {
+            pattern,
+            patterns,
+            params,
+            settings,
+            ..
+        } => (patterns, pattern, params, settings),
+        r => fail(&format!("unexpected reply {:?}", r)),
+    }
+}
+
+fn pattern_name(client: &mut Port, n: u8) -> String {
+    match request(client, &Request::GetPattern(n)) {
+        Response::Pattern { name, .. } => name.to_string(),
+        r => fail(&format!("unexpected reply {:?}", r)),
+    }
+}
+
+// Name, and a line describing it
+fn param(client: &mut Port, req: &Request) -> (String, String) {
+    match request(client, req) {
+        Response::Param {
+            name,
+            min,
+            max,
+            step,
+            value,
+            ..
+        } => {
+            let line = format!("{} = {} ({}..{} step {})", name, value, min, max, step);
+            (name.to_string(), line)
+        }
+        r => fail(&format!("unexpected reply {:?}", r)),
+    }
+}
+
+fn print_params(client: &mut Port) {
+    let (_, _, params, settings) = state(client);
+    for n in 0..params {
+        println!("{}", param(client, &Request::GetParam(n)).1);
+    }
+    for n in 0..settings {
+        println!("{}", param(client, &Request::GetSetting(n)).1);
+    }
+}
+
+fn select(client: &mut Port, which: &str) {
+    let n = match which.parse() {
+        Ok(n) => n,
+        Err(_) => {
+            let (patterns, ..) = state(client);
+            (0..patterns)
+                .find(|&n| pattern_name(client, n) == which)
+                .unwrap_or_else(|| fail(&format!("no pattern {:?}", which)))
+        }
+    };
+    request(client, &Request::Select(n));
+}
+
+fn set(client: &mut Port, name: &str, value: i16) {
+    let (_, _, params, settings) = state(client);
+    for n in 0..params {
+        if param(client, &Request::GetParam(n)).0 == name {
+            request(client, &Request::SetParam { param: n, value });
+            return;
+        }
+    }
+    for n in 0..settings {
+        if param(client, &Request::GetSetting(n)).0 == name {
+            request(client, &Request::SetSetting { param: n, value });
+            return;
+        }
+    }
+    fail(&format!("no parameter or setting {:?}", name));
+}
+
+// The last LEDS words of a line, each rrggbb
+fn parse_frame(line: &str) -> Option<Vec<u8>> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let colors = words.get(words.len().checked_sub(LEDS)?..)?;
+    let mut rgb = Vec::with_capacity(LEDS * 3);
+    for w in colors {
+        let c = u32::from_str_radix(w, 16).ok().filter(|_| w.len() == 6)?;
+        rgb.extend_from_slice(&[(c >> 16) as u8, (c >> 8) as u8, c as u8]);
+    }
+    Some(rgb)
+}
+
+fn stream(client: &mut Port, path: &str, fps: u32) {
+    let input: Box<dyn BufRead> = match path {
+        "-" => Box::new(BufReader::new(io::stdin())),
+        path => match File::open(path) {
+            Ok(f) => Box::new(BufReader::new(f)),
+            Err(e) => fail(&format!("{}: {}", path, e)),
+        },
+    };
+    let period = Duration::from_secs(1) / fps;
+    let mut next = Instant::now();
+    for (n, line) in input.lines().enumerate() {
+        let line = line.unwrap_or_else(|e| fail(&format!("read error: {}", e)));
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let rgb = parse_frame(line)
+            .unwrap_or_else(|| fail(&format!("line {}: can't parse a frame", n + 1)));
+        request(client, &Request::PushFrame(&rgb));
+        next += period;
+        let now = Instant::now();
+        if next > now {
+            sleep(next - now);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
+    if args.len() < 2 {
+        fail(USAGE);
+    }
+    let port = serialport::new(args[0], 115_200)
+        .timeout(Duration::from_millis(500))
+        .open()
+        .unwrap_or_else(|e| fail(&format!("{}: {}", args[0], e)));
+    let mut client = Client::new(port);
+    let client = &mut client;
+    match &args[1..] {
+        ["state"] => println!("{:?}", request(client, &Request::GetState)),
+        ["patterns"] => {
+            let (patterns, active, ..) = state(client);
+            for n in 0..patterns {
+                let mark = if n == active { '*' } else { ' ' };
+                println!("{} {} {}", mark, n, pattern_name(client, n));
+            }
+        }
+        ["params"] => print_params(client),
+        ["select", which] => select(client, which),
+        ["set", name, value] => {
+            let value = value.parse().unwrap_or_else(|_| fail(USAGE));
+            set(client, name, value);
+        }
+        ["stream", path] => stream(client, path, FPS),
+        ["stream", path, fps] => match fps.parse() {
+            Ok(fps) if fps > 0 => stream(client, path, fps),
+            _ => fail(USAGE),
+        },
+        ["diag"] => println!("{:?}", request(client, &Request::GetDiag)),
+        _ => fail(USAGE),
+    }
+}
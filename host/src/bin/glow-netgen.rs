@@ -0,0 +1,59 @@
+// Sends a moving test pattern as Art-Net or sACN, standing in for a desk
+// when trying glow-bridge.
+//
+//   glow-netgen artnet|sacn [HOST] [UNIVERSE] [FPS]
+//
+// HOST defaults to 127.0.0.1 and FPS to 30. UNIVERSE defaults to the first
+// one, which is 0 for Art-Net and 1 for sACN. One LED at a time is lit,
+// patched from channel 1, in red, green then blue on each lap.
+use std::env;
+use std::net::UdpSocket;
+use std::process::exit;
+use std::thread::sleep;
+use std::time::Duration;
+
+use glow::m6::LEDS;
+use glow_host::net::{self, ARTNET_PORT, SACN_PORT, SLOTS};
+
+const USAGE: &str = "usage: glow-netgen artnet|sacn [HOST] [UNIVERSE] [FPS]";
+
+// Builds a packet from the universe, a sequence number and the data
+type Packet = fn(u16, u8, &[u8]) -> Vec<u8>;
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let (packet, port, first): (Packet, u16, u16) = match args.first().map(|s| s.as_str()) {
+        Some("artnet") => (net::artnet_packet, ARTNET_PORT, 0),
+        Some("sacn") => (net::sacn_packet, SACN_PORT, 1),
+        _ => fail(USAGE),
+    };
+    let host = args.get(1).map(|s| s.as_str()).unwrap_or("127.0.0.1");
+    let universe: u16 = match args.get(2).map(|s| s.parse()) {
+        None => first,
+        Some(Ok(u)) => u,
+        Some(Err(_)) => fail(USAGE),
+    };
+    let fps: u32 = match args.get(3).map(|s| s.parse()) {
+        None => 30,
+        Some(Ok(f)) if f > 0 => f,
+        Some(_) => fail(USAGE),
+    };
+    let socket = UdpSocket::bind("0.0.0.0:0").unwrap_or_else(|e| fail(&e.to_string()));
+    let _ = socket.set_broadcast(true);
+
+    for frame in 0u32.. {
+        let mut data = [0; SLOTS];
+        let (lap, led) = (frame as usize / LEDS, frame as usize % LEDS);
+        data[led * 3 + lap % 3] = 255;
+        let p = packet(universe, frame as u8, &data);
+        if let Err(e) = socket.send_to(&p, (host, port)) {
+            fail(&format!("{}: {}", host, e));
+        }
+        sleep(Duration::from_secs(1) / fps);
+    }
+}
@@ -0,0 +1,123 @@
+// Replays an input recording dumped by the firmware (a long press on the
+// second button), printing every frame from the first recorded event on as
+// one line of hex LED colors. Replays start from a fresh app at boot, or
+// from the dump's state line at its frame; a snapshot doesn't hold the
+// animation phase, so the colors may differ from the board's until the
+// patterns next restart. Dumps that lost events with no snapshot to start
+// from are refused.
+//
+//   glow-replay [FILE] [EXTRA_FRAMES]
+//
+// Reads stdin when FILE is missing or "-".
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::exit;
+
+use glow::app::App;
+use glow::input::Mapping;
+use glow::m6::LEDS;
+use glow::record::{Record, Snapshot, STATE, TRUNCATED};
+use smart_leds::RGB8;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let input: Box<dyn BufRead> = match args.first().map(|s| s.as_str()) {
+        None | Some("-") => Box::new(BufReader::new(io::stdin())),
+        Some(path) => match File::open(path) {
+            Ok(f) => Box::new(BufReader::new(f)),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                exit(1);
+            }
+        },
+    };
+    let extra: u32 = match args.get(1).map(|s| s.parse()) {
+        None => 0,
+        Some(Ok(n)) => n,
+        Some(Err(_)) => {
+            eprintln!("usage: glow-replay [FILE] [EXTRA_FRAMES]");
+            exit(2);
+        }
+    };
+
+    let mut snapshot = None;
+    let mut records = Vec::new();
+    for (n, line) in input.lines().enumerate() {
+        let line = line.unwrap_or_else(|e| {
+            eprintln!("read error: {}", e);
+            exit(1);
+        });
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        // Comments, e.g. diagnostics reported while the dump was captured
+        if line.starts_with(TRUNCATED) {
+            eprintln!(
+                "line {}: {}; a replay from the start would not match",
+                n + 1,
+                line[1..].trim()
+            );
+            exit(1);
+        }
+        if line.starts_with('#') {
+            continue;
+        }
+        if line.starts_with(STATE) {
+            match line.parse::<Snapshot>() {
+                Ok(s) => snapshot = Some(s),
+                Err(_) => {
+                    eprintln!("line {}: can't parse {:?}", n + 1, line);
+                    exit(1);
+                }
+            }
+            continue;
+        }
+        match line.parse::<Record>() {
+            Ok(r) => records.push(r),
+            Err(_) => {
+                eprintln!("line {}: can't parse {:?}", n + 1, line);
+                exit(1);
+            }
+        }
+    }
+    let (first, last) = match (records.first(), records.last()) {
+        (Some(a), Some(b)) => (a.frame, b.frame),
+        _ => {
+            eprintln!("no records");
+            exit(1);
+        }
+    };
+
+    let mut app = App::new(Mapping::new());
+    let start = match snapshot {
+        Some(s) => {
+            s.apply(&mut app);
+            s.frame
+        }
+        None => 0,
+    };
+    let mut leds = [RGB8::default(); LEDS];
+    let mut pending = records.iter().peekable();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for frame in start..=(last + extra) {
+        while let Some(r) = pending.peek() {
+            if r.frame > frame {
+                break;
+            }
+            // Dumps only matter on the board
+            let _ = app.input(r.event);
+            pending.next();
+        }
+        app.frame(&mut leds);
+        if frame >= first {
+            let _ = write!(out, "{}", frame);
+            for c in leds.iter() {
+                let _ = write!(out, " {:02x}{:02x}{:02x}", c.r, c.g, c.b);
+            }
+            let _ = writeln!(out);
+        }
+    }
+}
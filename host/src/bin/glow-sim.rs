@@ -0,0 +1,54 @@
+// Runs the firmware's patterns and binary protocol on the host behind a
+// pseudo-terminal, so glow-ctl and other tools can be tried without a board.
+//
+//   glow-sim
+//
+// Prints the path of the terminal to connect to, then runs at the firmware
+// frame rate until killed.
+use std::io::{self, Read, Write};
+use std::process::exit;
+use std::time::{Duration, Instant};
+
+use glow::settings::FPS;
+use glow_host::sim::Sim;
+use serialport::{SerialPort, TTYPort};
+
+fn main() {
+    let (mut master, slave) = TTYPort::pair().unwrap_or_else(|e| {
+        eprintln!("can't open a pseudo-terminal: {}", e);
+        exit(1);
+    });
+    // The slave stays open so the pair survives clients coming and going
+    println!("{}", slave.name().unwrap_or_default());
+    let _ = io::stdout().flush();
+
+    let period = Duration::from_secs(1) / FPS;
+    let mut sim = Sim::new();
+    let mut next = Instant::now() + period;
+    let mut buf = [0; 256];
+    let mut out = Vec::new();
+    loop {
+        let now = Instant::now();
+        if now >= next {
+            sim.frame();
+            next += period;
+            continue;
+        }
+        let _ = master.set_timeout(next - now);
+        match master.read(&mut buf) {
+            Ok(n) => sim.receive(&buf[..n], &mut out),
+            Err(ref e) if e.kind() == io::ErrorKind::TimedOut => {}
+            Err(e) => {
+                eprintln!("read error: {}", e);
+                exit(1);
+            }
+        }
+        if !out.is_empty() {
+            if let Err(e) = master.write_all(&out) {
+                eprintln!("write error: {}", e);
+                exit(1);
+            }
+            out.clear();
+        }
+    }
+}
@@ -0,0 +1,56 @@
+// Host side of the binary protocol in glow-proto, over any byte stream such
+// as a serial port
+use std::io::{self, Read, Write};
+
+pub mod net;
+pub mod sim;
+
+use glow_proto::{Error, FrameReader, Packet, Request, Response, MAX_FRAME};
+
+pub struct Client<P> {
+    port: P,
+    next_id: u8,
+    reader: FrameReader,
+    reply: Option<Packet>,
+}
+
+fn invalid(e: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+impl<P: Read + Write> Client<P> {
+    pub fn new(port: P) -> Self {
+        Self {
+            port,
+            next_id: 0,
+            reader: FrameReader::new(),
+            reply: None,
+        }
+    }
+    // Sends a request and waits for the reply carrying its id, skipping
+    // stale replies and corrupt frames; the port's read timeout bounds the
+    // wait
+    pub fn request(&mut self, req: &Request) -> io::Result<Response<'_>> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        let mut buf = [0; MAX_FRAME];
+        let n = req.encode(id, &mut buf).map_err(invalid)?;
+        self.port.write_all(&buf[..n])?;
+        self.port.flush()?;
+        let mut byte = [0];
+        let reply = loop {
+            if self.port.read(&mut byte)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            match self.reader.push(byte[0]) {
+                Some(Ok(p)) if p.id() == id => break p,
+                _ => {}
+            }
+        };
+        self.reply = Some(reply);
+        self.reply.as_ref().unwrap().response().map_err(invalid)
+    }
+    pub fn into_inner(self) -> P {
+        self.port
+    }
+}
@@ -0,0 +1,208 @@
+// DMX over UDP as sent by lighting desks: Art-Net's ArtDmx and sACN
+// (E1.31) data packets. Only what the bridge needs is checked; polls,
+// sync packets and the like are ignored.
+use glow::m6::LEDS;
+
+pub const ARTNET_PORT: u16 = 6454;
+pub const SACN_PORT: u16 = 5568;
+pub const SLOTS: usize = 512;
+
+const ARTNET_ID: &[u8] = b"Art-Net\0";
+const OP_DMX: u16 = 0x5000;
+const ARTNET_VERSION: u16 = 14;
+const ARTNET_HEADER: usize = 18;
+
+const ACN_ID: &[u8] = b"ASC-E1.17\0\0\0";
+// Start of the DMX data, after the start code
+const SACN_HEADER: usize = 126;
+// Framing options
+const PREVIEW: u8 = 0x40;
+const TERMINATED: u8 = 0x20;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dmx<'a> {
+    pub universe: u16,
+    pub data: &'a [u8],
+}
+
+fn be16(b: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([b[at], b[at + 1]])
+}
+
+fn be32(b: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([b[at], b[at + 1], b[at + 2], b[at + 3]])
+}
+
+// Universes are the 15 bit port address, net, subnet and universe
+pub fn parse_artnet(b: &[u8]) -> Option<Dmx<'_>> {
+    if b.len() < ARTNET_HEADER || !b.starts_with(ARTNET_ID) {
+        return None;
+    }
+    if u16::from_le_bytes([b[8], b[9]]) != OP_DMX {
+        return None;
+    }
+    let universe = u16::from_le_bytes([b[14], b[15]]) & 0x7fff;
+    let len = (be16(b, 16) as usize).min(SLOTS);
+    let data = b.get(ARTNET_HEADER..ARTNET_HEADER + len)?;
+    Some(Dmx { universe, data })
+}
+
+// Preview data and streams being terminated are skipped, as are
+// alternate start codes
+pub fn parse_sacn(b: &[u8]) -> Option<Dmx<'_>> {
+    if b.len() < SACN_HEADER || be16(b, 0) != 0x10 || &b[4..16] != ACN_ID {
+        return None;
+    }
+    // Root, framing and DMP layer vectors
+    if be32(b, 18) != 4 || be32(b, 40) != 2 || b[117] != 2 {
+        return None;
+    }
+    if b[112] & (PREVIEW | TERMINATED) != 0 || b[125] != 0 {
+        return None;
+    }
+    let universe = be16(b, 113);
+    // Counts the start code
+    let count = (be16(b, 123) as usize).saturating_sub(1).min(SLOTS);
+    let data = b.get(SACN_HEADER..SACN_HEADER + count)?;
+    Some(Dmx { universe, data })
+}
+
+pub fn artnet_packet(universe: u16, sequence: u8, data: &[u8]) -> Vec<u8> {
+    let mut p = ARTNET_ID.to_vec();
+    p.extend_from_slice(&OP_DMX.to_le_bytes());
+    p.extend_from_slice(&ARTNET_VERSION.to_be_bytes());
+    p.extend_from_slice(&[sequence, 0]);
+    p.extend_from_slice(&universe.to_le_bytes());
+    p.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    p.extend_from_slice(data);
+    p
+}
+
+pub fn sacn_packet(universe: u16, sequence: u8, data: &[u8]) -> Vec<u8> {
+    // Each PDU length counts from its own flags and length field
+    let pdu = |len: usize| (0x7000 | len as u16).to_be_bytes();
+    let end = SACN_HEADER + data.len();
+    let mut p = Vec::with_capacity(end);
+    p.extend_from_slice(&[0, 0x10, 0, 0]);
+    p.extend_from_slice(ACN_ID);
+    p.extend_from_slice(&pdu(end - 16));
+    p.extend_from_slice(&4u32.to_be_bytes());
+    // CID, any fixed UUID will do for a test source
+    p.extend_from_slice(b"glow-netgen\0\0\0\0\0");
+    p.extend_from_slice(&pdu(end - 38));
+    p.extend_from_slice(&2u32.to_be_bytes());
+    let mut name = [0; 64];
+    name[..11].copy_from_slice(b"glow-netgen");
+    p.extend_from_slice(&name);
+    // Priority, sync address, sequence, options and universe
+    p.extend_from_slice(&[100, 0, 0, sequence, 0]);
+    p.extend_from_slice(&universe.to_be_bytes());
+    p.extend_from_slice(&pdu(end - 115));
+    // Vector, address type, first address and increment
+    p.extend_from_slice(&[2, 0xa1, 0, 0, 0, 1]);
+    p.extend_from_slice(&(data.len() as u16 + 1).to_be_bytes());
+    p.push(0);
+    p.extend_from_slice(data);
+    p
+}
+
+// Where the LEDs sit in the universe: three channels each, red first,
+// from `channel` on, counting from 1 like a desk does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Patch {
+    universe: u16,
+    channel: u16,
+}
+
+impl Patch {
+    // None unless the channel is within the universe
+    pub fn new(universe: u16, channel: u16) -> Option<Self> {
+        if channel < 1 || channel as usize > SLOTS {
+            return None;
+        }
+        Some(Self { universe, channel })
+    }
+    pub fn universe(&self) -> u16 {
+        self.universe
+    }
+    // RGB bytes for every LED, black past the end of the data
+    pub fn frame(&self, dmx: &Dmx) -> Option<[u8; LEDS * 3]> {
+        if dmx.universe != self.universe {
+            return None;
+        }
+        let mut rgb = [0; LEDS * 3];
+        let from = dmx.data.get(self.channel as usize - 1..).unwrap_or(&[]);
+        for (out, &b) in rgb.iter_mut().zip(from) {
+            *out = b;
+        }
+        Some(rgb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data() -> Vec<u8> {
+        (0..SLOTS).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn artnet_round_trip() {
+        let data = data();
+        let p = artnet_packet(0x0102, 9, &data);
+        let dmx = parse_artnet(&p).unwrap();
+        assert_eq!(
+            dmx,
+            Dmx {
+                universe: 0x0102,
+                data: &data
+            }
+        );
+        let rgb = Patch::new(0x0102, 1).unwrap().frame(&dmx).unwrap();
+        assert_eq!(rgb[..], data[..LEDS * 3]);
+        assert_eq!(Patch::new(0x0103, 1).unwrap().frame(&dmx), None);
+        // The top bit isn't part of the port address
+        let p = artnet_packet(0x8001, 0, &data[..6]);
+        assert_eq!(parse_artnet(&p).map(|d| d.universe), Some(1));
+        assert_eq!(parse_sacn(&p), None);
+    }
+
+    #[test]
+    fn sacn_round_trip() {
+        let data = data();
+        let mut p = sacn_packet(7, 0, &data[..100]);
+        let dmx = parse_sacn(&p).unwrap();
+        assert_eq!(
+            dmx,
+            Dmx {
+                universe: 7,
+                data: &data[..100]
+            }
+        );
+        // Patched near the end of the data, the rest is black
+        let rgb = Patch::new(7, 91).unwrap().frame(&dmx).unwrap();
+        assert_eq!(rgb[..10], data[90..100]);
+        assert!(rgb[10..].iter().all(|&b| b == 0));
+        assert_eq!(parse_artnet(&p), None);
+        p[112] |= PREVIEW;
+        assert_eq!(parse_sacn(&p), None);
+        p[112] &= !PREVIEW;
+        p[125] = 0xdd;
+        assert_eq!(parse_sacn(&p), None);
+        assert_eq!(parse_sacn(&p[..SACN_HEADER - 1]), None);
+    }
+
+    #[test]
+    fn patches_stay_in_the_universe() {
+        assert_eq!(Patch::new(1, 0), None);
+        assert_eq!(Patch::new(1, SLOTS as u16 + 1), None);
+        // The last channel, so only the first LED's red is set
+        let data = data();
+        let p = sacn_packet(1, 0, &data);
+        let dmx = parse_sacn(&p).unwrap();
+        let rgb = Patch::new(1, SLOTS as u16).unwrap().frame(&dmx).unwrap();
+        assert_eq!(rgb[0], data[SLOTS - 1]);
+        assert!(rgb[1..].iter().all(|&b| b == 0));
+    }
+}
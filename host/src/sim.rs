@@ -0,0 +1,76 @@
+// The device without its hardware: the patterns, settings and binary
+// protocol of the firmware, clocked by the host
+use glow::app::App;
+use glow::input::{InputEvent, Mapping};
+use glow::m6::LEDS;
+use glow::remote::{self, Reply};
+use glow::settings::{estimate_ma, FPS};
+use glow_proto::{FrameReader, Request, Response, Status, MAX_FRAME};
+use smart_leds::RGB8;
+
+pub struct Sim {
+    pub app: App,
+    reader: FrameReader,
+    leds: [RGB8; LEDS],
+}
+
+impl Sim {
+    pub fn new() -> Self {
+        Self {
+            app: App::new(Mapping::new()),
+            reader: FrameReader::new(),
+            leds: [RGB8::default(); LEDS],
+        }
+    }
+    // Feeds bytes from the host, appending reply frames to `out`
+    pub fn receive(&mut self, bytes: &[u8], out: &mut Vec<u8>) {
+        for &b in bytes {
+            let packet = match self.reader.push(b) {
+                Some(Ok(packet)) => packet,
+                _ => continue,
+            };
+            let response = match packet.request() {
+                Ok(req) => self.respond(&req),
+                Err(_) => Response::Nack(Status::BadRequest),
+            };
+            let mut buf = [0; MAX_FRAME];
+            if let Ok(n) = response.encode(packet.id(), &mut buf) {
+                out.extend_from_slice(&buf[..n]);
+            }
+        }
+    }
+    fn respond(&mut self, req: &Request) -> Response<'static> {
+        match remote::handle(req, &self.app) {
+            Reply::Answer(r) => r,
+            Reply::Dispatch(action) => {
+                // Dumps have nowhere to go
+                let _ = self.app.input(InputEvent::Command(action));
+                Response::Ack
+            }
+            Reply::Push => match req {
+                Request::PushFrame(rgb) if self.app.push_frame(rgb) => Response::Ack,
+                _ => Response::Nack(Status::BadRequest),
+            },
+            Reply::Diag => Response::Diag {
+                display_up: false,
+                i2c_errors: 0,
+                fps: FPS,
+                overruns: 0,
+                render_us: 0,
+                write_us: 0,
+                ma: estimate_ma(self.app.last_frame()),
+            },
+        }
+    }
+    // Renders the next frame, as the tick task would
+    pub fn frame(&mut self) -> &[RGB8; LEDS] {
+        self.app.frame(&mut self.leds);
+        &self.leds
+    }
+}
+
+impl Default for Sim {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,69 @@
+// Runs glow-sim and talks to it through its pseudo-terminal with Client,
+// the way glow-ctl does
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command, Stdio};
+use std::time::Duration;
+
+use glow::m6::LEDS;
+use glow_host::Client;
+use glow_proto::{Request, Response, Status};
+
+// Kills the simulator however the test ends
+struct Sim(Child);
+
+impl Drop for Sim {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn client_talks_to_the_simulator() {
+    let mut sim = Sim(Command::new(env!("CARGO_BIN_EXE_glow-sim"))
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("can't run glow-sim"));
+    let mut path = String::new();
+    BufReader::new(sim.0.stdout.take().unwrap())
+        .read_line(&mut path)
+        .unwrap();
+    let port = serialport::new(path.trim(), 115_200)
+        .timeout(Duration::from_secs(2))
+        .open()
+        .expect("can't open the simulator's terminal");
+    let mut client = Client::new(port);
+
+    assert_eq!(client.request(&Request::Ping).unwrap(), Response::Ack);
+    assert_eq!(client.request(&Request::Select(2)).unwrap(), Response::Ack);
+    match client.request(&Request::GetState).unwrap() {
+        Response::State {
+            pattern, streaming, ..
+        } => {
+            assert_eq!(pattern, 2);
+            assert!(!streaming);
+        }
+        r => panic!("unexpected reply {:?}", r),
+    }
+    match client.request(&Request::GetPattern(2)).unwrap() {
+        Response::Pattern { name, .. } => assert_eq!(name, "zoom"),
+        r => panic!("unexpected reply {:?}", r),
+    }
+    assert_eq!(
+        client.request(&Request::Select(200)).unwrap(),
+        Response::Nack(Status::OutOfRange)
+    );
+
+    // A frame full of 0 bytes, the worst case for the framing
+    let rgb = vec![0; LEDS * 3];
+    let push = Request::PushFrame(&rgb);
+    assert_eq!(client.request(&push).unwrap(), Response::Ack);
+    match client.request(&Request::GetState).unwrap() {
+        Response::State { streaming, .. } => assert!(streaming),
+        r => panic!("unexpected reply {:?}", r),
+    }
+    assert_eq!(
+        client.request(&Request::PushFrame(&rgb[1..])).unwrap(),
+        Response::Nack(Status::BadRequest)
+    );
+}
@@ -1,6 +1,7 @@
 /* Linker script for the STM32F103C8T6 */
 MEMORY
 {
-  FLASH : ORIGIN = 0x08000000, LENGTH = 128K
+  /* The last 2K hold the settings store, see src/flash.rs */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 126K
   RAM : ORIGIN = 0x20000000, LENGTH = 20K
 }
\ No newline at end of file
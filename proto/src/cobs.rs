@@ -0,0 +1,64 @@
+use crate::Error;
+
+// Worst case size of `len` bytes once encoded, without the delimiter
+pub const fn max_encoded_len(len: usize) -> usize {
+    len + len / 254 + 1
+}
+
+fn set(dst: &mut [u8], i: usize, b: u8) -> Result<(), Error> {
+    *dst.get_mut(i).ok_or(Error::Overflow)? = b;
+    Ok(())
+}
+
+// Replaces every 0 byte with the distance to the next one, so that 0 can
+// delimit frames; returns the encoded length
+pub fn encode(src: &[u8], dst: &mut [u8]) -> Result<usize, Error> {
+    let mut code_at = 0;
+    let mut code = 1u8;
+    let mut out = 1;
+    for &b in src {
+        if b != 0 {
+            set(dst, out, b)?;
+            out += 1;
+            code += 1;
+        }
+        if b == 0 || code == 0xff {
+            set(dst, code_at, code)?;
+            code_at = out;
+            out += 1;
+            code = 1;
+        }
+    }
+    set(dst, code_at, code)?;
+    Ok(out)
+}
+
+// Decodes a frame without its delimiter; the output never overtakes the
+// input, so one buffer does for both. Returns the decoded length.
+pub fn decode_in_place(buf: &mut [u8]) -> Result<usize, Error> {
+    let mut i = 0;
+    let mut out = 0;
+    while i < buf.len() {
+        let code = buf[i] as usize;
+        if code == 0 {
+            return Err(Error::Malformed);
+        }
+        let end = i + code;
+        if end > buf.len() {
+            return Err(Error::Malformed);
+        }
+        for j in (i + 1)..end {
+            if buf[j] == 0 {
+                return Err(Error::Malformed);
+            }
+            buf[out] = buf[j];
+            out += 1;
+        }
+        i = end;
+        if code < 0xff && i < buf.len() {
+            buf[out] = 0;
+            out += 1;
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,25 @@
+// CRC-16/CCITT-FALSE: polynomial 0x1021, initial value 0xffff, not
+// reflected; "123456789" gives 0x29b1
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0xffff;
+    for &b in data {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn check_value() {
+        assert_eq!(super::crc16(b"123456789"), 0x29b1);
+        assert_eq!(super::crc16(&[]), 0xffff);
+    }
+}
@@ -0,0 +1,221 @@
+#![no_std]
+// Every frame is one COBS encoded packet followed by a 0 byte. A packet is
+//
+//   id: u8, type: u8, payload..., crc: u16
+//
+// with the CRC-16/CCITT-FALSE of everything before it, little endian like
+// all the numbers in the payload. Replies carry the id of their request.
+// The device reads a byte at a time without a FIFO, so hosts wait for each
+// reply before sending the next request.
+pub mod cobs;
+pub mod crc;
+pub mod message;
+
+pub use message::{Packet, Request, Response, Status};
+
+// Longest packet, enough for a full frame of LEDs
+pub const MAX_PACKET: usize = 128;
+// Longest frame on the wire, with the COBS overhead and the delimiter
+pub const MAX_FRAME: usize = cobs::max_encoded_len(MAX_PACKET) + 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // Doesn't fit the buffer
+    Overflow,
+    // Not valid COBS
+    Malformed,
+    Crc,
+    // Too short to hold an id, a type and a CRC
+    Short,
+    UnknownType,
+    BadPayload,
+}
+
+// Collects bytes up to a frame delimiter
+pub struct FrameReader {
+    buf: [u8; MAX_FRAME],
+    len: usize,
+    overflow: bool,
+}
+
+impl FrameReader {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; MAX_FRAME],
+            len: 0,
+            overflow: false,
+        }
+    }
+    // Returns the packet, checked and without its CRC, at the end of each
+    // frame; empty frames are skipped, so a 0 byte resynchronises
+    pub fn push(&mut self, b: u8) -> Option<Result<Packet, Error>> {
+        if b != 0 {
+            match self.buf.get_mut(self.len) {
+                Some(slot) => {
+                    *slot = b;
+                    self.len += 1;
+                }
+                None => self.overflow = true,
+            }
+            return None;
+        }
+        let (len, overflow) = (self.len, self.overflow);
+        self.len = 0;
+        self.overflow = false;
+        if len == 0 {
+            return None;
+        }
+        if overflow {
+            return Some(Err(Error::Overflow));
+        }
+        Some(self.packet(len))
+    }
+    fn packet(&mut self, len: usize) -> Result<Packet, Error> {
+        let n = cobs::decode_in_place(&mut self.buf[..len])?;
+        if n < 4 {
+            return Err(Error::Short);
+        }
+        let (data, crc) = self.buf[..n].split_at(n - 2);
+        if crc::crc16(data) != u16::from_le_bytes([crc[0], crc[1]]) {
+            return Err(Error::Crc);
+        }
+        Packet::new(data)
+    }
+}
+
+impl Default for FrameReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feeds the bytes, returning the result of the last frame
+    fn read(r: &mut FrameReader, bytes: &[u8]) -> Option<Result<Packet, Error>> {
+        let mut rv = None;
+        for &b in bytes {
+            if let Some(res) = r.push(b) {
+                rv = Some(res);
+            }
+        }
+        rv
+    }
+
+    #[test]
+    fn requests_round_trip() {
+        let rgb = [0, 1, 0, 0, 255, 0];
+        let reqs = [
+            Request::Ping,
+            Request::GetPattern(2),
+            Request::SetParam {
+                param: 0,
+                value: -300,
+            },
+            Request::SetSetting { param: 3, value: 0 },
+            Request::PushFrame(&rgb),
+            Request::GetDiag,
+        ];
+        let mut r = FrameReader::default();
+        for (id, req) in reqs.iter().enumerate() {
+            let mut buf = [0; MAX_FRAME];
+            let n = req.encode(id as u8, &mut buf).unwrap();
+            // Only the delimiter is 0, whatever the payload
+            assert_eq!(buf[..n].iter().position(|&b| b == 0), Some(n - 1));
+            let packet = read(&mut r, &buf[..n]).unwrap().unwrap();
+            assert_eq!(packet.id(), id as u8);
+            assert_eq!(packet.request(), Ok(*req));
+        }
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let resps = [
+            Response::Ack,
+            Response::Nack(Status::OutOfRange),
+            Response::Param {
+                index: 1,
+                name: "saturation",
+                min: 0,
+                max: 255,
+                step: 8,
+                value: 0,
+            },
+            Response::Diag {
+                display_up: true,
+                i2c_errors: 0,
+                fps: 30,
+                overruns: 0x0100_0000,
+                render_us: 812,
+                write_us: 0,
+                ma: 450,
+            },
+        ];
+        let mut r = FrameReader::new();
+        for resp in resps.iter() {
+            let mut buf = [0; MAX_FRAME];
+            let n = resp.encode(0, &mut buf).unwrap();
+            let packet = read(&mut r, &buf[..n]).unwrap().unwrap();
+            assert_eq!(packet.response(), Ok(*resp));
+        }
+    }
+
+    #[test]
+    fn largest_packet_fits_a_frame() {
+        // Everything 0, the worst case for COBS
+        let rgb = [0; MAX_PACKET - 4];
+        let mut buf = [0; MAX_FRAME];
+        let n = Request::PushFrame(&rgb).encode(0, &mut buf).unwrap();
+        let packet = read(&mut FrameReader::new(), &buf[..n]).unwrap().unwrap();
+        assert_eq!(packet.request(), Ok(Request::PushFrame(&rgb)));
+        let rgb = [0; MAX_PACKET - 3];
+        let req = Request::PushFrame(&rgb);
+        assert_eq!(req.encode(0, &mut buf), Err(Error::Overflow));
+    }
+
+    #[test]
+    fn rejects_bad_frames() {
+        let mut buf = [0; MAX_FRAME];
+        let n = Request::Select(1).encode(7, &mut buf).unwrap();
+        let mut r = FrameReader::new();
+        // A flipped bit, which still decodes as COBS
+        buf[3] ^= 0x04;
+        assert_eq!(
+            read(&mut r, &buf[..n]).map(|p| p.err()),
+            Some(Some(Error::Crc))
+        );
+        // Too long for the buffer
+        assert!(read(&mut r, &[1; 200]).is_none());
+        assert_eq!(
+            read(&mut r, &[0]).map(|p| p.err()),
+            Some(Some(Error::Overflow))
+        );
+        // A code byte pointing past the end
+        assert_eq!(
+            read(&mut r, &[5, 1, 0]).map(|p| p.err()),
+            Some(Some(Error::Malformed))
+        );
+        assert_eq!(
+            read(&mut r, &[3, 1, 2, 0]).map(|p| p.err()),
+            Some(Some(Error::Short))
+        );
+    }
+
+    #[test]
+    fn resyncs_after_garbage() {
+        let mut buf = [0; MAX_FRAME];
+        let n = Request::GetState.encode(9, &mut buf).unwrap();
+        let mut r = FrameReader::new();
+        // The tail of a frame cut short, ended by the next one's delimiter
+        assert!(read(&mut r, &[0x42, 0x13, 0x37]).is_none());
+        assert!(r.push(0).unwrap().is_err());
+        let packet = read(&mut r, &buf[..n]).unwrap().unwrap();
+        assert_eq!(packet.request(), Ok(Request::GetState));
+        // Repeated delimiters are empty frames, skipped
+        assert!(read(&mut r, &[0, 0, 0]).is_none());
+        let packet = read(&mut r, &buf[..n]).unwrap().unwrap();
+        assert_eq!(packet.id(), 9);
+    }
+}
@@ -0,0 +1,376 @@
+use crate::{cobs, crc, Error, MAX_PACKET};
+
+// A checked packet without its CRC, as returned by FrameReader
+#[derive(Clone, Copy)]
+pub struct Packet {
+    len: usize,
+    data: [u8; MAX_PACKET],
+}
+
+impl Packet {
+    pub fn new(bytes: &[u8]) -> Result<Self, Error> {
+        let mut data = [0; MAX_PACKET];
+        data.get_mut(..bytes.len())
+            .ok_or(Error::Overflow)?
+            .copy_from_slice(bytes);
+        let len = bytes.len();
+        Ok(Self { len, data })
+    }
+    pub fn id(&self) -> u8 {
+        self.data[0]
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+    pub fn request(&self) -> Result<Request<'_>, Error> {
+        Request::decode(self.as_slice())
+    }
+    pub fn response(&self) -> Result<Response<'_>, Error> {
+        Response::decode(self.as_slice())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request<'a> {
+    Ping,
+    GetState,
+    GetPattern(u8),
+    // Of the active pattern
+    GetParam(u8),
+    GetSetting(u8),
+    Select(u8),
+    SetParam { param: u8, value: i16 },
+    SetSetting { param: u8, value: i16 },
+    // RGB bytes for every LED in strip order, shown until the pushes stop
+    PushFrame(&'a [u8]),
+    GetDiag,
+}
+
+// Answers a request with the same id
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Response<'a> {
+    Ack,
+    Nack(Status),
+    State {
+        pattern: u8,
+        patterns: u8,
+        params: u8,
+        settings: u8,
+        streaming: bool,
+    },
+    Pattern {
+        index: u8,
+        name: &'a str,
+        params: u8,
+    },
+    // A pattern parameter or a setting
+    Param {
+        index: u8,
+        name: &'a str,
+        min: i16,
+        max: i16,
+        step: i16,
+        value: i16,
+    },
+    Diag {
+        display_up: bool,
+        i2c_errors: u32,
+        fps: u32,
+        overruns: u32,
+        render_us: u32,
+        write_us: u32,
+        ma: u32,
+    },
+}
+
+// Why a request was refused
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    // Didn't decode, or can't be carried out as sent
+    BadRequest = 1,
+    OutOfRange = 2,
+    // Decoded, but not available on this device
+    Unsupported = 3,
+}
+
+impl Status {
+    fn from_u8(b: u8) -> Result<Self, Error> {
+        match b {
+            1 => Ok(Status::BadRequest),
+            2 => Ok(Status::OutOfRange),
+            3 => Ok(Status::Unsupported),
+            _ => Err(Error::BadPayload),
+        }
+    }
+}
+
+// Builds a packet, then frames it
+struct Writer {
+    buf: [u8; MAX_PACKET],
+    len: usize,
+}
+
+impl Writer {
+    fn new(id: u8, ty: u8) -> Self {
+        let mut buf = [0; MAX_PACKET];
+        buf[0] = id;
+        buf[1] = ty;
+        Self { buf, len: 2 }
+    }
+    fn bytes(&mut self, b: &[u8]) -> Result<&mut Self, Error> {
+        self.buf
+            .get_mut(self.len..self.len + b.len())
+            .ok_or(Error::Overflow)?
+            .copy_from_slice(b);
+        self.len += b.len();
+        Ok(self)
+    }
+    fn u8(&mut self, v: u8) -> Result<&mut Self, Error> {
+        self.bytes(&[v])
+    }
+    fn i16(&mut self, v: i16) -> Result<&mut Self, Error> {
+        self.bytes(&v.to_le_bytes())
+    }
+    fn u32(&mut self, v: u32) -> Result<&mut Self, Error> {
+        self.bytes(&v.to_le_bytes())
+    }
+    fn str(&mut self, s: &str) -> Result<&mut Self, Error> {
+        if s.len() > 255 {
+            return Err(Error::Overflow);
+        }
+        self.u8(s.len() as u8)?.bytes(s.as_bytes())
+    }
+    // Appends the CRC and COBS encodes into `out`, delimiter included
+    fn frame(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        let crc = crc::crc16(&self.buf[..self.len]);
+        self.bytes(&crc.to_le_bytes())?;
+        let n = cobs::encode(&self.buf[..self.len], out)?;
+        *out.get_mut(n).ok_or(Error::Overflow)? = 0;
+        Ok(n + 1)
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        if n > self.buf.len() {
+            return Err(Error::BadPayload);
+        }
+        let (b, rest) = self.buf.split_at(n);
+        self.buf = rest;
+        Ok(b)
+    }
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.bytes(1)?[0])
+    }
+    fn i16(&mut self) -> Result<i16, Error> {
+        let b = self.bytes(2)?;
+        Ok(i16::from_le_bytes([b[0], b[1]]))
+    }
+    fn u32(&mut self) -> Result<u32, Error> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    fn str(&mut self) -> Result<&'a str, Error> {
+        let n = self.u8()? as usize;
+        core::str::from_utf8(self.bytes(n)?).map_err(|_| Error::BadPayload)
+    }
+    fn rest(&mut self) -> &'a [u8] {
+        self.bytes(self.buf.len()).unwrap_or(&[])
+    }
+    fn end(&self) -> Result<(), Error> {
+        if !self.buf.is_empty() {
+            return Err(Error::BadPayload);
+        }
+        Ok(())
+    }
+}
+
+// Splits a packet into its type and a reader for the payload
+fn open(packet: &[u8]) -> Result<(u8, Reader<'_>), Error> {
+    if packet.len() < 2 {
+        return Err(Error::Short);
+    }
+    Ok((packet[1], Reader { buf: &packet[2..] }))
+}
+
+impl<'a> Request<'a> {
+    // Writes the whole frame for this request into `out`
+    pub fn encode(&self, id: u8, out: &mut [u8]) -> Result<usize, Error> {
+        use Request::*;
+        let mut w;
+        match *self {
+            Ping => w = Writer::new(id, 0x01),
+            GetState => w = Writer::new(id, 0x02),
+            GetPattern(n) => {
+                w = Writer::new(id, 0x03);
+                w.u8(n)?;
+            }
+            GetParam(n) => {
+                w = Writer::new(id, 0x04);
+                w.u8(n)?;
+            }
+            GetSetting(n) => {
+                w = Writer::new(id, 0x05);
+                w.u8(n)?;
+            }
+            Select(n) => {
+                w = Writer::new(id, 0x06);
+                w.u8(n)?;
+            }
+            SetParam { param, value } => {
+                w = Writer::new(id, 0x07);
+                w.u8(param)?.i16(value)?;
+            }
+            SetSetting { param, value } => {
+                w = Writer::new(id, 0x08);
+                w.u8(param)?.i16(value)?;
+            }
+            PushFrame(rgb) => {
+                w = Writer::new(id, 0x09);
+                w.bytes(rgb)?;
+            }
+            GetDiag => w = Writer::new(id, 0x0a),
+        }
+        w.frame(out)
+    }
+    pub fn decode(packet: &'a [u8]) -> Result<Self, Error> {
+        use Request::*;
+        let (ty, mut r) = open(packet)?;
+        let req = match ty {
+            0x01 => Ping,
+            0x02 => GetState,
+            0x03 => GetPattern(r.u8()?),
+            0x04 => GetParam(r.u8()?),
+            0x05 => GetSetting(r.u8()?),
+            0x06 => Select(r.u8()?),
+            0x07 => SetParam {
+                param: r.u8()?,
+                value: r.i16()?,
+            },
+            0x08 => SetSetting {
+                param: r.u8()?,
+                value: r.i16()?,
+            },
+            0x09 => PushFrame(r.rest()),
+            0x0a => GetDiag,
+            _ => return Err(Error::UnknownType),
+        };
+        r.end()?;
+        Ok(req)
+    }
+}
+
+impl<'a> Response<'a> {
+    pub fn encode(&self, id: u8, out: &mut [u8]) -> Result<usize, Error> {
+        use Response::*;
+        let mut w;
+        match *self {
+            Ack => w = Writer::new(id, 0x81),
+            Nack(status) => {
+                w = Writer::new(id, 0x82);
+                w.u8(status as u8)?;
+            }
+            State {
+                pattern,
+                patterns,
+                params,
+                settings,
+                streaming,
+            } => {
+                w = Writer::new(id, 0x83);
+                w.u8(pattern)?
+                    .u8(patterns)?
+                    .u8(params)?
+                    .u8(settings)?
+                    .u8(streaming as u8)?;
+            }
+            Pattern {
+                index,
+                name,
+                params,
+            } => {
+                w = Writer::new(id, 0x84);
+                w.u8(index)?.str(name)?.u8(params)?;
+            }
+            Param {
+                index,
+                name,
+                min,
+                max,
+                step,
+                value,
+            } => {
+                w = Writer::new(id, 0x85);
+                w.u8(index)?
+                    .str(name)?
+                    .i16(min)?
+                    .i16(max)?
+                    .i16(step)?
+                    .i16(value)?;
+            }
+            Diag {
+                display_up,
+                i2c_errors,
+                fps,
+                overruns,
+                render_us,
+                write_us,
+                ma,
+            } => {
+                w = Writer::new(id, 0x86);
+                w.u8(display_up as u8)?
+                    .u32(i2c_errors)?
+                    .u32(fps)?
+                    .u32(overruns)?
+                    .u32(render_us)?
+                    .u32(write_us)?
+                    .u32(ma)?;
+            }
+        }
+        w.frame(out)
+    }
+    pub fn decode(packet: &'a [u8]) -> Result<Self, Error> {
+        use Response::*;
+        let (ty, mut r) = open(packet)?;
+        let resp = match ty {
+            0x81 => Ack,
+            0x82 => Nack(Status::from_u8(r.u8()?)?),
+            0x83 => State {
+                pattern: r.u8()?,
+                patterns: r.u8()?,
+                params: r.u8()?,
+                settings: r.u8()?,
+                streaming: r.u8()? != 0,
+            },
+            0x84 => Pattern {
+                index: r.u8()?,
+                name: r.str()?,
+                params: r.u8()?,
+            },
+            0x85 => Param {
+                index: r.u8()?,
+                name: r.str()?,
+                min: r.i16()?,
+                max: r.i16()?,
+                step: r.i16()?,
+                value: r.i16()?,
+            },
+            0x86 => Diag {
+                display_up: r.u8()? != 0,
+                i2c_errors: r.u32()?,
+                fps: r.u32()?,
+                overruns: r.u32()?,
+                render_us: r.u32()?,
+                write_us: r.u32()?,
+                ma: r.u32()?,
+            },
+            _ => return Err(Error::UnknownType),
+        };
+        r.end()?;
+        Ok(resp)
+    }
+}
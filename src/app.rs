@@ -0,0 +1,194 @@
+use smart_leds::RGB8;
+
+use crate::display::Level;
+use crate::input::{Action, InputEvent, Mapping};
+use crate::m6::{Generator, Render, LEDS};
+use crate::menu::Menu;
+use crate::preset::{Preset, PRESETS};
+use crate::render::Patterns;
+use crate::settings::{Settings, FPS};
+use crate::tempo::Tempo;
+
+// Frames a pushed frame stays up without another push
+const STREAM_TIMEOUT: u32 = FPS;
+// Frames without input before changes are worth saving, so turning a knob
+// doesn't wear the flash
+const SAVE_DELAY: u32 = 5 * FPS;
+
+// Actions the firmware has to carry out itself
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request {
+    Dump,
+}
+
+// Everything input events can change, so that the firmware and a host
+// replay of its recording behave the same
+pub struct App {
+    pub patterns: Patterns,
+    pub settings: Settings,
+    pub menu: Menu,
+    // Fed by MIDI clock, which isn't recorded, so replays only match while
+    // it is absent
+    pub tempo: Tempo,
+    mapping: Mapping,
+    // Cleared to keep the LEDs dark
+    on: bool,
+    // Frames since the pattern last changed by itself
+    cycle: u32,
+    // Frames since the last input
+    idle: u32,
+    // Input since the last save
+    unsaved: bool,
+    last: [RGB8; LEDS],
+    presets: [Option<Preset>; PRESETS],
+    // Pushed from the host; shown instead of the pattern while `stream_left`
+    stream: [RGB8; LEDS],
+    stream_left: u32,
+}
+
+impl App {
+    pub const fn new(mapping: Mapping) -> Self {
+        let patterns = Patterns::new();
+        let settings = Settings::new();
+        let menu = Menu::new();
+        let tempo = Tempo::new();
+        let on = true;
+        let cycle = 0;
+        let idle = 0;
+        let unsaved = false;
+        let last = [RGB8 { r: 0, g: 0, b: 0 }; LEDS];
+        let presets = [None; PRESETS];
+        let stream = last;
+        let stream_left = 0;
+        Self {
+            patterns,
+            settings,
+            menu,
+            tempo,
+            mapping,
+            on,
+            cycle,
+            idle,
+            unsaved,
+            last,
+            presets,
+            stream,
+            stream_left,
+        }
+    }
+    // The open menu takes the knobs and buttons, otherwise they go through
+    // the mapping to the patterns
+    pub fn input(&mut self, ev: InputEvent) -> Option<Request> {
+        let asleep = self.screen_level() == Level::Off;
+        self.idle = 0;
+        self.unsaved = true;
+        // Knobs and buttons only wake a blank screen, so nothing changes
+        // unseen
+        match ev {
+            InputEvent::Rotate { .. } | InputEvent::Button { .. } if asleep => {
+                return None;
+            }
+            _ => {}
+        }
+        if self.menu.input(ev, &mut self.patterns, &mut self.settings) {
+            return None;
+        }
+        match self.mapping.map(ev)? {
+            Action::Dump => return Some(Request::Dump),
+            Action::Menu => self.menu.open(),
+            Action::Setting { param, value } => self.settings.set(param as usize, value),
+            Action::AdjustSetting { param, delta } => self.settings.adjust(param as usize, delta),
+            Action::Power => self.on = !self.on,
+            Action::Save(slot) => {
+                if let Some(p) = self.presets.get_mut(slot as usize) {
+                    *p = Some(Preset::capture(&self.patterns));
+                }
+            }
+            Action::Load(slot) => {
+                if let Some(p) = self.preset(slot) {
+                    p.apply(&mut self.patterns);
+                }
+            }
+            action => self.patterns.handle(action),
+        }
+        None
+    }
+    // Shows RGB bytes for every LED until the pushes stop for a while;
+    // false if there are too few or too many
+    pub fn push_frame(&mut self, rgb: &[u8]) -> bool {
+        if rgb.len() != LEDS * 3 {
+            return false;
+        }
+        for (led, c) in self.stream.iter_mut().zip(rgb.chunks(3)) {
+            *led = RGB8 {
+                r: c[0],
+                g: c[1],
+                b: c[2],
+            };
+        }
+        self.stream_left = STREAM_TIMEOUT;
+        true
+    }
+    pub fn is_streaming(&self) -> bool {
+        self.stream_left > 0
+    }
+    // Renders the current frame, or the pushed one, limited by the
+    // settings, then steps the active pattern and the auto cycle
+    pub fn frame(&mut self, leds: &mut [RGB8; LEDS]) {
+        if self.stream_left > 0 {
+            self.stream_left -= 1;
+            *leds = self.stream;
+        } else {
+            for (led, c) in leds.iter_mut().zip(Generator::new(&self.patterns)) {
+                *led = c;
+            }
+        }
+        if !self.on {
+            *leds = [RGB8::default(); LEDS];
+        }
+        self.settings.limit(leds);
+        self.last = *leds;
+        for _ in 0..self.tempo.steps() {
+            self.patterns.tick();
+        }
+        self.idle = self.idle.saturating_add(1);
+        let every = self.settings.auto_cycle_frames();
+        self.cycle += 1;
+        if every == 0 {
+            self.cycle = 0;
+        } else if self.cycle >= every {
+            self.cycle = 0;
+            self.patterns.handle(Action::NextPattern);
+        }
+    }
+    pub fn screen_level(&self) -> Level {
+        self.settings.screen_level(self.idle)
+    }
+    pub fn is_on(&self) -> bool {
+        self.on
+    }
+    pub fn set_on(&mut self, on: bool) {
+        self.on = on;
+    }
+    pub fn preset(&self, slot: u8) -> Option<Preset> {
+        *self.presets.get(slot as usize)?
+    }
+    pub fn set_preset(&mut self, slot: u8, preset: Preset) {
+        if let Some(p) = self.presets.get_mut(slot as usize) {
+            *p = Some(preset);
+        }
+    }
+    // True once input has settled after a change, to save it; later calls
+    // are false until there is more
+    pub fn take_unsaved(&mut self) -> bool {
+        let due = self.unsaved && self.idle >= SAVE_DELAY;
+        if due {
+            self.unsaved = false;
+        }
+        due
+    }
+    // As last written to the strip
+    pub fn last_frame(&self) -> &[RGB8; LEDS] {
+        &self.last
+    }
+}
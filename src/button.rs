@@ -0,0 +1,285 @@
+use embedded_hal::digital::v2::InputPin;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture {
+    Click,
+    DoubleClick,
+    LongPress,
+    // Knob turned while the button was held; no click follows the release
+    HoldTurn(i16),
+}
+
+// All durations in the same ticks as the `now` passed to poll
+#[derive(Clone, Copy, Debug)]
+pub struct Timing {
+    pub debounce: u32,
+    pub double_click: u32,
+    pub long_press: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum State {
+    Idle,
+    Pressed {
+        since: u32,
+        second: bool,
+        spent: bool,
+    },
+    Released {
+        since: u32,
+    },
+}
+
+pub struct Button<P: InputPin> {
+    pin: P,
+    active_low: bool,
+    timing: Timing,
+    pressed: bool,
+    changed: u32,
+    state: State,
+}
+
+impl<P: InputPin> Button<P> {
+    pub fn new(pin: P, active_low: bool, timing: Timing) -> Self {
+        let pressed = false;
+        let changed = 0;
+        let state = State::Idle;
+        Self {
+            pin,
+            active_low,
+            timing,
+            pressed,
+            changed,
+            state,
+        }
+    }
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+    // Call on every pin edge and periodically, so that timeouts fire while
+    // the pin is idle
+    pub fn poll(&mut self, now: u32) -> Option<Gesture> {
+        // Timeouts that passed unpolled come first, so a press after the
+        // double click window is a new click rather than the second half
+        // of a double. The timeout and the edge never both make a gesture.
+        let timed = self.timeout(now);
+        let level = self.pin.is_high().unwrap_or(self.active_low);
+        let raw = level != self.active_low;
+        if raw != self.pressed && now.wrapping_sub(self.changed) >= self.timing.debounce {
+            self.pressed = raw;
+            self.changed = now;
+            let edge = if raw {
+                self.press(now)
+            } else {
+                self.release(now)
+            };
+            return timed.or(edge);
+        }
+        timed
+    }
+    // Report a knob turn; returns a gesture if it was a hold-while-turning
+    pub fn turned(&mut self, delta: i16) -> Option<Gesture> {
+        match &mut self.state {
+            State::Pressed { spent, .. } => {
+                *spent = true;
+                Some(Gesture::HoldTurn(delta))
+            }
+            _ => None,
+        }
+    }
+    fn press(&mut self, now: u32) -> Option<Gesture> {
+        let second = match self.state {
+            State::Released { .. } => true,
+            _ => false,
+        };
+        self.state = State::Pressed {
+            since: now,
+            second,
+            spent: false,
+        };
+        None
+    }
+    fn release(&mut self, now: u32) -> Option<Gesture> {
+        let (rv, next) = match self.state {
+            State::Pressed { spent: true, .. } => (None, State::Idle),
+            State::Pressed { second: true, .. } => (Some(Gesture::DoubleClick), State::Idle),
+            State::Pressed { .. } => (None, State::Released { since: now }),
+            s => (None, s),
+        };
+        self.state = next;
+        rv
+    }
+    fn timeout(&mut self, now: u32) -> Option<Gesture> {
+        match &mut self.state {
+            State::Pressed { since, spent, .. } => {
+                if !*spent && now.wrapping_sub(*since) >= self.timing.long_press {
+                    *spent = true;
+                    return Some(Gesture::LongPress);
+                }
+                None
+            }
+            State::Released { since } => {
+                if now.wrapping_sub(*since) >= self.timing.double_click {
+                    self.state = State::Idle;
+                    return Some(Gesture::Click);
+                }
+                None
+            }
+            State::Idle => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    struct Pin<'a>(&'a Cell<bool>);
+
+    impl<'a> InputPin for Pin<'a> {
+        type Error = ();
+        fn is_high(&self) -> Result<bool, ()> {
+            Ok(self.0.get())
+        }
+        fn is_low(&self) -> Result<bool, ()> {
+            Ok(!self.0.get())
+        }
+    }
+
+    const TIMING: Timing = Timing {
+        debounce: 5,
+        double_click: 300,
+        long_press: 600,
+    };
+
+    // Runs a script of (time, pin level) with `None` for a poll without a
+    // change, returning the gestures with their times
+    fn run(
+        button: &mut Button<Pin>,
+        pin: &Cell<bool>,
+        script: &[(u32, Option<bool>)],
+    ) -> [Option<(u32, Gesture)>; 4] {
+        let mut rv = [None; 4];
+        let mut n = 0;
+        for &(now, level) in script {
+            if let Some(level) = level {
+                pin.set(level);
+            }
+            if let Some(g) = button.poll(now) {
+                rv[n] = Some((now, g));
+                n += 1;
+            }
+        }
+        rv
+    }
+
+    #[test]
+    fn click_after_the_double_click_window() {
+        let pin = Cell::new(false);
+        let mut b = Button::new(Pin(&pin), false, TIMING);
+        let script = [
+            (10, Some(true)),
+            (110, Some(false)),
+            (409, None),
+            (410, None),
+        ];
+        assert_eq!(
+            run(&mut b, &pin, &script),
+            [Some((410, Gesture::Click)), None, None, None]
+        );
+    }
+
+    #[test]
+    fn double_click() {
+        let pin = Cell::new(false);
+        let mut b = Button::new(Pin(&pin), false, TIMING);
+        let script = [
+            (10, Some(true)),
+            (110, Some(false)),
+            (210, Some(true)),
+            (310, Some(false)),
+            (1010, None),
+        ];
+        assert_eq!(
+            run(&mut b, &pin, &script),
+            [Some((310, Gesture::DoubleClick)), None, None, None]
+        );
+    }
+
+    #[test]
+    fn late_second_press_is_a_new_click() {
+        // Nothing polls between the release and the next press, which comes
+        // after the double click window
+        let pin = Cell::new(false);
+        let mut b = Button::new(Pin(&pin), false, TIMING);
+        let script = [
+            (10, Some(true)),
+            (110, Some(false)),
+            (510, Some(true)),
+            (610, Some(false)),
+            (910, None),
+        ];
+        assert_eq!(
+            run(&mut b, &pin, &script),
+            [
+                Some((510, Gesture::Click)),
+                Some((910, Gesture::Click)),
+                None,
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn long_press_without_click() {
+        let pin = Cell::new(false);
+        let mut b = Button::new(Pin(&pin), false, TIMING);
+        let script = [
+            (10, Some(true)),
+            (609, None),
+            (610, None),
+            (710, None),
+            (810, Some(false)),
+            (2010, None),
+        ];
+        assert_eq!(
+            run(&mut b, &pin, &script),
+            [Some((610, Gesture::LongPress)), None, None, None]
+        );
+    }
+
+    #[test]
+    fn hold_turn_spends_the_click() {
+        let pin = Cell::new(false);
+        let mut b = Button::new(Pin(&pin), false, TIMING);
+        assert_eq!(b.turned(1), None);
+        pin.set(true);
+        assert_eq!(b.poll(10), None);
+        assert!(b.is_pressed());
+        assert_eq!(b.turned(-2), Some(Gesture::HoldTurn(-2)));
+        let script = [(110, Some(false)), (1010, None)];
+        assert_eq!(run(&mut b, &pin, &script), [None; 4]);
+    }
+
+    #[test]
+    fn bounces_are_ignored() {
+        // Active low, bouncing within the debounce time on each edge
+        let pin = Cell::new(true);
+        let mut b = Button::new(Pin(&pin), true, TIMING);
+        let script = [
+            (20, Some(false)),
+            (22, Some(true)),
+            (23, Some(false)),
+            (110, Some(true)),
+            (112, Some(false)),
+            (113, Some(true)),
+            (510, None),
+        ];
+        assert_eq!(
+            run(&mut b, &pin, &script),
+            [Some((510, Gesture::Click)), None, None, None]
+        );
+        assert!(!b.is_pressed());
+    }
+}
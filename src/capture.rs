@@ -0,0 +1,64 @@
+use stm32f1xx_hal::pac::{TIM2, TIM3, TIM4};
+
+// Put a general purpose timer into input capture of both edges on CH1: IC1
+// takes the falling edges and IC2, also mapped to TI1, the rising ones.
+// Ticks are `prescaler` timer clocks long. The timer's clock and the CH1
+// pin must already be enabled and configured as an input.
+pub trait EdgeCapture: Sized {
+    fn edge_capture(self, prescaler: u16) -> Self;
+    // The next captured edge, rising or not, and its count; clears its
+    // interrupt
+    fn edge(&self) -> Option<(bool, u16)>;
+}
+
+macro_rules! edge_capture {
+    ($($TIM:ident,)+) => {
+        $(
+            impl EdgeCapture for $TIM {
+                fn edge_capture(self, prescaler: u16) -> Self {
+                    self.cr1.modify(|_r, w| w.cen().clear_bit());
+                    self.psc.write(|w| unsafe { w.psc().bits(prescaler - 1) });
+                    self.arr.write(|w| w.arr().bits(0xffff));
+                    // Both from TI1, filtered over 8 samples at fDTS/8
+                    self.ccmr1_input().write(|w| unsafe {
+                        w.cc1s()
+                            .bits(0b01)
+                            .ic1f()
+                            .bits(0b1001)
+                            .cc2s()
+                            .bits(0b10)
+                            .ic2f()
+                            .bits(0b1001)
+                    });
+                    self.ccer.write(|w| {
+                        w.cc1e()
+                            .set_bit()
+                            .cc1p()
+                            .set_bit()
+                            .cc2e()
+                            .set_bit()
+                            .cc2p()
+                            .clear_bit()
+                    });
+                    self.dier.write(|w| w.cc1ie().set_bit().cc2ie().set_bit());
+                    self.egr.write(|w| w.ug().set_bit());
+                    self.cr1.modify(|_r, w| w.cen().set_bit());
+                    self
+                }
+                fn edge(&self) -> Option<(bool, u16)> {
+                    let sr = self.sr.read();
+                    // Reading the capture clears its flag
+                    if sr.cc1if().bit_is_set() {
+                        Some((false, self.ccr1.read().ccr1().bits()))
+                    } else if sr.cc2if().bit_is_set() {
+                        Some((true, self.ccr2.read().ccr2().bits()))
+                    } else {
+                        None
+                    }
+                }
+            }
+        )+
+    };
+}
+
+edge_capture!(TIM2, TIM3, TIM4,);
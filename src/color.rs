@@ -0,0 +1,386 @@
+use core::fmt;
+use core::str::FromStr;
+
+use smart_leds::RGB8;
+
+use crate::hsv::HSV;
+use crate::palette::hex;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseError {
+    Empty,
+    BadHex,
+    BadNumber,
+    BadArgs,
+    UnknownName,
+}
+
+// A color as written in text: `#rrggbb` or `#rgb`, `rgb(r,g,b)`,
+// `hsv(h,s,v)` or a CSS color name. Hue in `hsv()` uses the same units as
+// `HSV::h`.
+#[derive(Clone, Copy, Debug)]
+pub enum Color {
+    Rgb(RGB8),
+    Hsv(HSV),
+}
+
+impl Color {
+    // Without gamma correction, like the palette entries
+    pub fn rgb(&self) -> RGB8 {
+        match self {
+            Color::Rgb(c) => *c,
+            Color::Hsv(c) => c.to_rgb_raw().into(),
+        }
+    }
+    pub fn hsv(&self) -> HSV {
+        match self {
+            Color::Rgb(c) => HSV::from_rgb(c.r, c.g, c.b),
+            Color::Hsv(c) => *c,
+        }
+    }
+}
+
+impl FromStr for Color {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(ParseError::Empty);
+        }
+        if s.starts_with('#') {
+            return parse_hex(&s[1..]).map(Color::Rgb);
+        }
+        if let Some(args) = call_args(s, "rgb") {
+            let [r, g, b] = parse_args(args)?;
+            return Ok(Color::Rgb(RGB8 {
+                r: to_u8(r)?,
+                g: to_u8(g)?,
+                b: to_u8(b)?,
+            }));
+        }
+        if let Some(args) = call_args(s, "hsv") {
+            let [h, s, v] = parse_args(args)?;
+            if h > i16::max_value() as u16 {
+                return Err(ParseError::BadNumber);
+            }
+            return Ok(Color::Hsv(HSV::new(h as i16, to_u8(s)?, to_u8(v)?)));
+        }
+        lookup(s).map(Color::Rgb).ok_or(ParseError::UnknownName)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Color::Rgb(c) => match name(*c) {
+                Some(n) => f.write_str(n),
+                None => write!(f, "#{:02x}{:02x}{:02x}", c.r, c.g, c.b),
+            },
+            Color::Hsv(c) => write!(f, "hsv({},{},{})", c.h, c.s, c.v),
+        }
+    }
+}
+
+fn parse_hex(s: &str) -> Result<RGB8, ParseError> {
+    if !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(ParseError::BadHex);
+    }
+    let n = u32::from_str_radix(s, 16).map_err(|_| ParseError::BadHex)?;
+    match s.len() {
+        6 => Ok(hex(n)),
+        3 => {
+            // Each digit doubled, as in CSS
+            let d = |shift: u32| ((n >> shift) & 0xf) * 0x11;
+            Ok(hex(d(8) << 16 | d(4) << 8 | d(0)))
+        }
+        _ => Err(ParseError::BadHex),
+    }
+}
+
+// Input is sliced with `get`, since a multibyte character may straddle
+// the end of `func`
+fn call_args<'a>(s: &'a str, func: &str) -> Option<&'a str> {
+    if !s.get(..func.len())?.eq_ignore_ascii_case(func) {
+        return None;
+    }
+    let rest = s.get(func.len()..)?.trim_start();
+    if rest.len() >= 2 && rest.starts_with('(') && rest.ends_with(')') {
+        Some(&rest[1..rest.len() - 1])
+    } else {
+        None
+    }
+}
+
+fn parse_args(args: &str) -> Result<[u16; 3], ParseError> {
+    let mut rv = [0; 3];
+    let mut parts = args.split(',');
+    for slot in rv.iter_mut() {
+        let p = parts.next().ok_or(ParseError::BadArgs)?;
+        *slot = p.trim().parse().map_err(|_| ParseError::BadNumber)?;
+    }
+    if parts.next().is_some() {
+        return Err(ParseError::BadArgs);
+    }
+    Ok(rv)
+}
+
+fn to_u8(n: u16) -> Result<u8, ParseError> {
+    if n > 255 {
+        return Err(ParseError::BadNumber);
+    }
+    Ok(n as u8)
+}
+
+pub fn lookup(name: &str) -> Option<RGB8> {
+    NAMED
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, c)| *c)
+}
+
+pub fn name(c: RGB8) -> Option<&'static str> {
+    NAMED.iter().find(|(_, n)| *n == c).map(|(n, _)| *n)
+}
+
+// CSS named colors, sorted by name
+pub const NAMED: [(&str, RGB8); 148] = [
+    ("aliceblue", hex(0xF0F8FF)),
+    ("antiquewhite", hex(0xFAEBD7)),
+    ("aqua", hex(0x00FFFF)),
+    ("aquamarine", hex(0x7FFFD4)),
+    ("azure", hex(0xF0FFFF)),
+    ("beige", hex(0xF5F5DC)),
+    ("bisque", hex(0xFFE4C4)),
+    ("black", hex(0x000000)),
+    ("blanchedalmond", hex(0xFFEBCD)),
+    ("blue", hex(0x0000FF)),
+    ("blueviolet", hex(0x8A2BE2)),
+    ("brown", hex(0xA52A2A)),
+    ("burlywood", hex(0xDEB887)),
+    ("cadetblue", hex(0x5F9EA0)),
+    ("chartreuse", hex(0x7FFF00)),
+    ("chocolate", hex(0xD2691E)),
+    ("coral", hex(0xFF7F50)),
+    ("cornflowerblue", hex(0x6495ED)),
+    ("cornsilk", hex(0xFFF8DC)),
+    ("crimson", hex(0xDC143C)),
+    ("cyan", hex(0x00FFFF)),
+    ("darkblue", hex(0x00008B)),
+    ("darkcyan", hex(0x008B8B)),
+    ("darkgoldenrod", hex(0xB8860B)),
+    ("darkgray", hex(0xA9A9A9)),
+    ("darkgreen", hex(0x006400)),
+    ("darkgrey", hex(0xA9A9A9)),
+    ("darkkhaki", hex(0xBDB76B)),
+    ("darkmagenta", hex(0x8B008B)),
+    ("darkolivegreen", hex(0x556B2F)),
+    ("darkorange", hex(0xFF8C00)),
+    ("darkorchid", hex(0x9932CC)),
+    ("darkred", hex(0x8B0000)),
+    ("darksalmon", hex(0xE9967A)),
+    ("darkseagreen", hex(0x8FBC8F)),
+    ("darkslateblue", hex(0x483D8B)),
+    ("darkslategray", hex(0x2F4F4F)),
+    ("darkslategrey", hex(0x2F4F4F)),
+    ("darkturquoise", hex(0x00CED1)),
+    ("darkviolet", hex(0x9400D3)),
+    ("deeppink", hex(0xFF1493)),
+    ("deepskyblue", hex(0x00BFFF)),
+    ("dimgray", hex(0x696969)),
+    ("dimgrey", hex(0x696969)),
+    ("dodgerblue", hex(0x1E90FF)),
+    ("firebrick", hex(0xB22222)),
+    ("floralwhite", hex(0xFFFAF0)),
+    ("forestgreen", hex(0x228B22)),
+    ("fuchsia", hex(0xFF00FF)),
+    ("gainsboro", hex(0xDCDCDC)),
+    ("ghostwhite", hex(0xF8F8FF)),
+    ("gold", hex(0xFFD700)),
+    ("goldenrod", hex(0xDAA520)),
+    ("gray", hex(0x808080)),
+    ("green", hex(0x008000)),
+    ("greenyellow", hex(0xADFF2F)),
+    ("grey", hex(0x808080)),
+    ("honeydew", hex(0xF0FFF0)),
+    ("hotpink", hex(0xFF69B4)),
+    ("indianred", hex(0xCD5C5C)),
+    ("indigo", hex(0x4B0082)),
+    ("ivory", hex(0xFFFFF0)),
+    ("khaki", hex(0xF0E68C)),
+    ("lavender", hex(0xE6E6FA)),
+    ("lavenderblush", hex(0xFFF0F5)),
+    ("lawngreen", hex(0x7CFC00)),
+    ("lemonchiffon", hex(0xFFFACD)),
+    ("lightblue", hex(0xADD8E6)),
+    ("lightcoral", hex(0xF08080)),
+    ("lightcyan", hex(0xE0FFFF)),
+    ("lightgoldenrodyellow", hex(0xFAFAD2)),
+    ("lightgray", hex(0xD3D3D3)),
+    ("lightgreen", hex(0x90EE90)),
+    ("lightgrey", hex(0xD3D3D3)),
+    ("lightpink", hex(0xFFB6C1)),
+    ("lightsalmon", hex(0xFFA07A)),
+    ("lightseagreen", hex(0x20B2AA)),
+    ("lightskyblue", hex(0x87CEFA)),
+    ("lightslategray", hex(0x778899)),
+    ("lightslategrey", hex(0x778899)),
+    ("lightsteelblue", hex(0xB0C4DE)),
+    ("lightyellow", hex(0xFFFFE0)),
+    ("lime", hex(0x00FF00)),
+    ("limegreen", hex(0x32CD32)),
+    ("linen", hex(0xFAF0E6)),
+    ("magenta", hex(0xFF00FF)),
+    ("maroon", hex(0x800000)),
+    ("mediumaquamarine", hex(0x66CDAA)),
+    ("mediumblue", hex(0x0000CD)),
+    ("mediumorchid", hex(0xBA55D3)),
+    ("mediumpurple", hex(0x9370DB)),
+    ("mediumseagreen", hex(0x3CB371)),
+    ("mediumslateblue", hex(0x7B68EE)),
+    ("mediumspringgreen", hex(0x00FA9A)),
+    ("mediumturquoise", hex(0x48D1CC)),
+    ("mediumvioletred", hex(0xC71585)),
+    ("midnightblue", hex(0x191970)),
+    ("mintcream", hex(0xF5FFFA)),
+    ("mistyrose", hex(0xFFE4E1)),
+    ("moccasin", hex(0xFFE4B5)),
+    ("navajowhite", hex(0xFFDEAD)),
+    ("navy", hex(0x000080)),
+    ("oldlace", hex(0xFDF5E6)),
+    ("olive", hex(0x808000)),
+    ("olivedrab", hex(0x6B8E23)),
+    ("orange", hex(0xFFA500)),
+    ("orangered", hex(0xFF4500)),
+    ("orchid", hex(0xDA70D6)),
+    ("palegoldenrod", hex(0xEEE8AA)),
+    ("palegreen", hex(0x98FB98)),
+    ("paleturquoise", hex(0xAFEEEE)),
+    ("palevioletred", hex(0xDB7093)),
+    ("papayawhip", hex(0xFFEFD5)),
+    ("peachpuff", hex(0xFFDAB9)),
+    ("peru", hex(0xCD853F)),
+    ("pink", hex(0xFFC0CB)),
+    ("plum", hex(0xDDA0DD)),
+    ("powderblue", hex(0xB0E0E6)),
+    ("purple", hex(0x800080)),
+    ("rebeccapurple", hex(0x663399)),
+    ("red", hex(0xFF0000)),
+    ("rosybrown", hex(0xBC8F8F)),
+    ("royalblue", hex(0x4169E1)),
+    ("saddlebrown", hex(0x8B4513)),
+    ("salmon", hex(0xFA8072)),
+    ("sandybrown", hex(0xF4A460)),
+    ("seagreen", hex(0x2E8B57)),
+    ("seashell", hex(0xFFF5EE)),
+    ("sienna", hex(0xA0522D)),
+    ("silver", hex(0xC0C0C0)),
+    ("skyblue", hex(0x87CEEB)),
+    ("slateblue", hex(0x6A5ACD)),
+    ("slategray", hex(0x708090)),
+    ("slategrey", hex(0x708090)),
+    ("snow", hex(0xFFFAFA)),
+    ("springgreen", hex(0x00FF7F)),
+    ("steelblue", hex(0x4682B4)),
+    ("tan", hex(0xD2B48C)),
+    ("teal", hex(0x008080)),
+    ("thistle", hex(0xD8BFD8)),
+    ("tomato", hex(0xFF6347)),
+    ("turquoise", hex(0x40E0D0)),
+    ("violet", hex(0xEE82EE)),
+    ("wheat", hex(0xF5DEB3)),
+    ("white", hex(0xFFFFFF)),
+    ("whitesmoke", hex(0xF5F5F5)),
+    ("yellow", hex(0xFFFF00)),
+    ("yellowgreen", hex(0x9ACD32)),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> Result<RGB8, ParseError> {
+        s.parse::<Color>().map(|c| c.rgb())
+    }
+
+    #[test]
+    fn names_sorted_and_found() {
+        for w in NAMED.windows(2) {
+            assert!(w[0].0 < w[1].0, "{} before {}", w[0].0, w[1].0);
+        }
+        for &(n, c) in NAMED.iter() {
+            assert_eq!(lookup(n), Some(c));
+            // Aliases such as aqua and cyan name the same color
+            assert_eq!(lookup(name(c).unwrap()), Some(c));
+        }
+        assert_eq!(parse("  RebeccaPurple "), Ok(hex(0x663399)));
+        assert_eq!(parse("nocolor"), Err(ParseError::UnknownName));
+        assert_eq!(parse(""), Err(ParseError::Empty));
+    }
+
+    #[test]
+    fn hex_forms() {
+        assert_eq!(parse("#ff8000"), Ok(hex(0xff8000)));
+        assert_eq!(parse("#A0b1C2"), Ok(hex(0xa0b1c2)));
+        assert_eq!(parse("#f80"), Ok(hex(0xff8800)));
+        assert_eq!(parse("#123"), Ok(hex(0x112233)));
+        assert_eq!(parse("#12345"), Err(ParseError::BadHex));
+        assert_eq!(parse("#gg0000"), Err(ParseError::BadHex));
+        assert_eq!(parse("#+12"), Err(ParseError::BadHex));
+        assert_eq!(parse("#"), Err(ParseError::BadHex));
+    }
+
+    #[test]
+    fn functional_forms() {
+        assert_eq!(parse("rgb(1, 2, 3)"), Ok(hex(0x010203)));
+        assert_eq!(parse("RGB (255,0,16)"), Ok(hex(0xff0010)));
+        assert_eq!(parse("rgb(256,0,0)"), Err(ParseError::BadNumber));
+        assert_eq!(parse("rgb(1,2)"), Err(ParseError::BadArgs));
+        assert_eq!(parse("rgb(1,2,3,4)"), Err(ParseError::BadArgs));
+        assert_eq!(parse("rgb(a,2,3)"), Err(ParseError::BadNumber));
+        assert_eq!(parse("rgb()"), Err(ParseError::BadNumber));
+        assert_eq!(parse("rgb("), Err(ParseError::UnknownName));
+        // A third of the way round is green
+        assert_eq!(parse("hsv(512,255,255)"), Ok(hex(0x00ff00)));
+        assert_eq!(parse("hsv(0,0,128)"), Ok(hex(0x808080)));
+        assert_eq!(parse("hsv(40000,0,0)"), Err(ParseError::BadNumber));
+    }
+
+    #[test]
+    fn multibyte_input() {
+        assert_eq!(parse("a€(1)"), Err(ParseError::UnknownName));
+        assert_eq!(parse("rg€"), Err(ParseError::UnknownName));
+        assert_eq!(parse("hs€(1,2,3)"), Err(ParseError::UnknownName));
+        assert_eq!(parse("#€"), Err(ParseError::BadHex));
+    }
+
+    #[test]
+    fn formatted() {
+        use core::fmt::Write;
+        let mut s: heapless::String<heapless::consts::U32> = heapless::String::new();
+        write!(
+            s,
+            "{} {}",
+            Color::Rgb(hex(0xff0000)),
+            Color::Rgb(hex(0x123456))
+        )
+        .unwrap();
+        assert_eq!(s.as_str(), "red #123456");
+        let mut s: heapless::String<heapless::consts::U32> = heapless::String::new();
+        write!(s, "{}", Color::Hsv(HSV::new(512, 255, 9))).unwrap();
+        assert_eq!(s.as_str(), "hsv(512,255,9)");
+    }
+
+    #[test]
+    fn hsv_round_trip() {
+        for r in (0..=255).step_by(15) {
+            for g in (0..=255).step_by(15) {
+                for b in (0..=255).step_by(15) {
+                    let (r2, g2, b2) = HSV::from_rgb(r, g, b).to_rgb_raw();
+                    let off = |x: u8, y: u8| (x as i16 - y as i16).abs();
+                    let worst = off(r, r2).max(off(g, g2)).max(off(b, b2));
+                    assert!(worst <= 2, "{:?} -> {:?}", (r, g, b), (r2, g2, b2));
+                }
+            }
+        }
+    }
+}
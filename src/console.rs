@@ -0,0 +1,443 @@
+use core::fmt::{self, Write};
+
+use heapless::{consts, String};
+
+use crate::app::App;
+use crate::color::Color;
+use crate::input::Action;
+use crate::m6::Render;
+use crate::preset::PRESETS;
+use crate::render::NAMES;
+
+const HELP: &str = "patterns | select NAME|N | params | get NAME | set NAME VALUE\r\n\
+                    brightness [VALUE] | color COLOR | save N | load N | diag";
+
+// Collects bytes into lines, with backspace; overlong lines are dropped
+pub struct LineBuffer {
+    line: String<consts::U64>,
+    overflow: bool,
+}
+
+impl LineBuffer {
+    pub fn new() -> Self {
+        let line = String::new();
+        let overflow = false;
+        Self { line, overflow }
+    }
+    // Returns the line once it is ended by CR or LF
+    pub fn push(&mut self, b: u8) -> Option<String<consts::U64>> {
+        match b {
+            b'\r' | b'\n' => {
+                let line = core::mem::replace(&mut self.line, String::new());
+                let overflow = self.overflow;
+                self.overflow = false;
+                if overflow || line.is_empty() {
+                    return None;
+                }
+                Some(line)
+            }
+            // Backspace and delete
+            8 | 127 => {
+                self.line.pop();
+                None
+            }
+            b' '..=b'~' => {
+                if self.line.push(b as char).is_err() {
+                    self.overflow = true;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+// Holds a reply while the app is locked; what doesn't fit is dropped and
+// noted rather than failing the write, so the action still goes through.
+// Sized for the longest reply, the params listing of the pattern with the
+// most parameters; `params_fit` checks it still fits.
+pub struct Reply {
+    text: String<consts::U512>,
+    truncated: bool,
+}
+
+impl Reply {
+    pub fn new() -> Self {
+        let text = String::new();
+        let truncated = false;
+        Self { text, truncated }
+    }
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl Write for Reply {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated || self.text.push_str(s).is_err() {
+            self.truncated = true;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command<'a> {
+    Help,
+    Patterns,
+    // By name or number
+    Select(&'a str),
+    Params,
+    // Pattern parameters and settings by name, which may contain spaces
+    Get(&'a str),
+    Set(&'a str, i16),
+    Brightness(Option<i16>),
+    // Any color `color::Color` reads, for the active pattern's hue
+    Color(&'a str),
+    Save(u8),
+    Load(u8),
+    // Answered by the firmware, which knows the state of the hardware
+    Diag,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParseError {
+    Empty,
+    Unknown,
+    BadArgs,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Empty => write!(f, "empty line"),
+            ParseError::Unknown => write!(f, "unknown command, try help"),
+            ParseError::BadArgs => write!(f, "bad arguments"),
+        }
+    }
+}
+
+fn num<T: core::str::FromStr>(s: &str) -> Result<T, ParseError> {
+    s.parse().map_err(|_| ParseError::BadArgs)
+}
+
+fn nonempty(s: &str) -> Result<&str, ParseError> {
+    if s.is_empty() {
+        return Err(ParseError::BadArgs);
+    }
+    Ok(s)
+}
+
+pub fn parse(line: &str) -> Result<Command<'_>, ParseError> {
+    let line = line.trim();
+    let (word, rest) = match line.find(' ') {
+        Some(i) => (&line[..i], line[i + 1..].trim()),
+        None => (line, ""),
+    };
+    let cmd = match word {
+        "" => return Err(ParseError::Empty),
+        "help" | "?" => Command::Help,
+        "patterns" => Command::Patterns,
+        "select" => Command::Select(nonempty(rest)?),
+        "params" => Command::Params,
+        "get" => Command::Get(nonempty(rest)?),
+        "set" => {
+            let i = rest.rfind(' ').ok_or(ParseError::BadArgs)?;
+            Command::Set(rest[..i].trim(), num(&rest[i + 1..])?)
+        }
+        "brightness" if rest.is_empty() => Command::Brightness(None),
+        "brightness" => Command::Brightness(Some(num(rest)?)),
+        "color" => Command::Color(nonempty(rest)?),
+        "save" => Command::Save(num(rest)?),
+        "load" => Command::Load(num(rest)?),
+        "diag" => Command::Diag,
+        _ => return Err(ParseError::Unknown),
+    };
+    Ok(cmd)
+}
+
+enum Target {
+    Param(u8),
+    Setting(u8),
+}
+
+// Parameters of the active pattern shadow settings of the same name
+fn lookup(app: &App, name: &str) -> Option<Target> {
+    let param = app.patterns.params().iter().position(|p| p.name == name);
+    let setting = app.settings.params().iter().position(|p| p.name == name);
+    match (param, setting) {
+        (Some(i), _) => Some(Target::Param(i as u8)),
+        (None, Some(i)) => Some(Target::Setting(i as u8)),
+        (None, None) => None,
+    }
+}
+
+// Writes the answer to a query, or "ok" and the action that makes a
+// change; the caller dispatches it like any other input
+pub fn execute(
+    cmd: Command,
+    app: &App,
+    out: &mut impl Write,
+) -> Result<Option<Action>, fmt::Error> {
+    let action = match cmd {
+        Command::Help => {
+            write!(out, "{}\r\n", HELP)?;
+            None
+        }
+        Command::Patterns => {
+            for (i, name) in NAMES.iter().enumerate() {
+                let on = if i == app.patterns.active() { " *" } else { "" };
+                write!(out, "{} {}{}\r\n", i, name, on)?;
+            }
+            None
+        }
+        Command::Select(s) => {
+            let idx = match s.parse::<usize>() {
+                Ok(i) => Some(i),
+                Err(_) => NAMES.iter().position(|n| *n == s),
+            };
+            match idx {
+                Some(i) if i < NAMES.len() => Some(Action::Select(i as u8)),
+                _ => {
+                    write!(out, "error: no pattern {}\r\n", s)?;
+                    None
+                }
+            }
+        }
+        Command::Params => {
+            let p = &app.patterns;
+            for (i, param) in p.params().iter().enumerate() {
+                write!(
+                    out,
+                    "{} {} ({}..{})\r\n",
+                    param.name,
+                    p.get(i),
+                    param.min,
+                    param.max
+                )?;
+            }
+            let s = &app.settings;
+            for (i, param) in s.params().iter().enumerate() {
+                write!(
+                    out,
+                    "{} {} ({}..{})\r\n",
+                    param.name,
+                    s.get(i),
+                    param.min,
+                    param.max
+                )?;
+            }
+            None
+        }
+        Command::Get(name) => {
+            match lookup(app, name) {
+                Some(Target::Param(i)) => write!(out, "{}\r\n", app.patterns.get(i as usize))?,
+                Some(Target::Setting(i)) => write!(out, "{}\r\n", app.settings.get(i as usize))?,
+                None => write!(out, "error: no parameter {}\r\n", name)?,
+            }
+            None
+        }
+        Command::Set(name, value) => match lookup(app, name) {
+            Some(Target::Param(param)) => Some(Action::Set { param, value }),
+            Some(Target::Setting(param)) => Some(Action::Setting { param, value }),
+            None => {
+                write!(out, "error: no parameter {}\r\n", name)?;
+                None
+            }
+        },
+        Command::Brightness(None) => {
+            write!(out, "{}\r\n", app.settings.brightness)?;
+            None
+        }
+        Command::Brightness(Some(value)) => Some(Action::Setting { param: 0, value }),
+        Command::Color(s) => {
+            let hue = app.patterns.params().iter().position(|p| p.name == "hue");
+            match (s.parse::<Color>(), hue) {
+                (Ok(c), Some(i)) => Some(Action::Set {
+                    param: i as u8,
+                    value: c.hsv().h as i16,
+                }),
+                (Err(_), _) => {
+                    write!(out, "error: no color {}\r\n", s)?;
+                    None
+                }
+                (Ok(_), None) => {
+                    write!(out, "error: {} has no hue\r\n", app.patterns.name())?;
+                    None
+                }
+            }
+        }
+        Command::Save(n) | Command::Load(n) if n as usize >= PRESETS => {
+            write!(out, "error: presets are 0..{}\r\n", PRESETS - 1)?;
+            None
+        }
+        Command::Save(n) => Some(Action::Save(n)),
+        Command::Load(n) => match app.preset(n) {
+            Some(_) => Some(Action::Load(n)),
+            None => {
+                write!(out, "error: preset {} is empty\r\n", n)?;
+                None
+            }
+        },
+        Command::Diag => None,
+    };
+    if action.is_some() {
+        write!(out, "ok\r\n")?;
+    }
+    Ok(action)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{InputEvent, Mapping};
+
+    fn lines(chunks: &[&[u8]]) -> ([Option<String<consts::U64>>; 4], usize) {
+        let mut buf = LineBuffer::new();
+        let mut rv = [None, None, None, None];
+        let mut n = 0;
+        for &b in chunks.iter().flat_map(|c| c.iter()) {
+            if let Some(line) = buf.push(b) {
+                rv[n] = Some(line);
+                n += 1;
+            }
+        }
+        (rv, n)
+    }
+
+    #[test]
+    fn collects_lines() {
+        let (rv, n) = lines(&[b"help\r\nsez\x08lect 1\r\x1b\r\n"]);
+        assert_eq!(n, 2);
+        assert_eq!(rv[0].as_ref().map(|l| l.as_str()), Some("help"));
+        assert_eq!(rv[1].as_ref().map(|l| l.as_str()), Some("select 1"));
+        // An overlong line is dropped whole, the next one is fine
+        let mut long = [b'x'; 70];
+        long[69] = b'\r';
+        let (_, n) = lines(&[&long]);
+        assert_eq!(n, 0);
+        let (rv, n) = lines(&[&long, b"diag\n"]);
+        assert_eq!(n, 1);
+        assert_eq!(rv[0].as_ref().map(|l| l.as_str()), Some("diag"));
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("  "), Err(ParseError::Empty));
+        assert_eq!(parse("?"), Ok(Command::Help));
+        assert_eq!(parse("select  zoom "), Ok(Command::Select("zoom")));
+        assert_eq!(parse("select"), Err(ParseError::BadArgs));
+        assert_eq!(parse("get power limit"), Ok(Command::Get("power limit")));
+        assert_eq!(
+            parse("set power limit 1500"),
+            Ok(Command::Set("power limit", 1500))
+        );
+        assert_eq!(parse("set speed -3"), Ok(Command::Set("speed", -3)));
+        assert_eq!(parse("set speed"), Err(ParseError::BadArgs));
+        assert_eq!(parse("set speed fast"), Err(ParseError::BadArgs));
+        assert_eq!(parse("brightness"), Ok(Command::Brightness(None)));
+        assert_eq!(parse("brightness 40"), Ok(Command::Brightness(Some(40))));
+        assert_eq!(parse("color #f80"), Ok(Command::Color("#f80")));
+        assert_eq!(parse("color"), Err(ParseError::BadArgs));
+        assert_eq!(parse("save 300"), Err(ParseError::BadArgs));
+        assert_eq!(parse("load 2"), Ok(Command::Load(2)));
+        assert_eq!(parse("selectzoom"), Err(ParseError::Unknown));
+    }
+
+    fn run(app: &mut App, line: &str) -> Reply {
+        let mut out = Reply::new();
+        let cmd = parse(line).unwrap();
+        match execute(cmd, app, &mut out).unwrap() {
+            Some(action) => {
+                app.input(InputEvent::Command(action));
+            }
+            None => {}
+        }
+        out
+    }
+
+    #[test]
+    fn executes_commands() {
+        let mut app = App::new(Mapping::new());
+        assert_eq!(run(&mut app, "select breath").as_str(), "ok\r\n");
+        assert_eq!(app.patterns.active(), 1);
+        assert_eq!(
+            run(&mut app, "select 9").as_str(),
+            "error: no pattern 9\r\n"
+        );
+        assert_eq!(run(&mut app, "set power limit 1500").as_str(), "ok\r\n");
+        assert_eq!(run(&mut app, "get power limit").as_str(), "1500\r\n");
+        assert_eq!(run(&mut app, "brightness 40").as_str(), "ok\r\n");
+        assert_eq!(run(&mut app, "brightness").as_str(), "40\r\n");
+        assert_eq!(
+            run(&mut app, "get nothing").as_str(),
+            "error: no parameter nothing\r\n"
+        );
+        assert_eq!(
+            run(&mut app, "load 3").as_str(),
+            "error: preset 3 is empty\r\n"
+        );
+        assert_eq!(
+            run(&mut app, "save 4").as_str(),
+            "error: presets are 0..3\r\n"
+        );
+        run(&mut app, "save 3");
+        run(&mut app, "select 0");
+        assert_eq!(run(&mut app, "load 3").as_str(), "ok\r\n");
+        assert_eq!(app.patterns.active(), 1);
+        let out = run(&mut app, "params");
+        assert!(!out.is_truncated());
+        assert!(out.as_str().ends_with("port 0 (0..2)\r\n"));
+    }
+
+    #[test]
+    fn colors_set_the_hue() {
+        let mut app = App::new(Mapping::new());
+        assert_eq!(
+            run(&mut app, "color red").as_str(),
+            "error: rainbow has no hue\r\n"
+        );
+        run(&mut app, "select breath");
+        assert_eq!(run(&mut app, "color rgb(0, 0, 255)").as_str(), "ok\r\n");
+        assert_eq!(run(&mut app, "get hue").as_str(), "1024\r\n");
+        assert_eq!(run(&mut app, "color #0f0").as_str(), "ok\r\n");
+        assert_eq!(run(&mut app, "get hue").as_str(), "512\r\n");
+        assert_eq!(
+            run(&mut app, "color rgb(1,2)").as_str(),
+            "error: no color rgb(1,2)\r\n"
+        );
+        assert_eq!(run(&mut app, "get hue").as_str(), "512\r\n");
+    }
+
+    #[test]
+    fn params_fit() {
+        let mut app = App::new(Mapping::new());
+        for (i, name) in NAMES.iter().enumerate() {
+            app.patterns.select(i);
+            let out = run(&mut app, "params");
+            assert!(!out.is_truncated(), "{}", name);
+            let lines = app.patterns.params().len() + app.settings.params().len();
+            assert_eq!(out.as_str().lines().count(), lines, "{}", name);
+        }
+    }
+
+    #[test]
+    fn long_replies_are_truncated() {
+        let mut out = Reply::new();
+        for _ in 0..51 {
+            write!(out, "0123456789").unwrap();
+        }
+        assert!(!out.is_truncated());
+        write!(out, "0123456789").unwrap();
+        write!(out, "0").unwrap();
+        assert!(out.is_truncated());
+        assert_eq!(out.as_str().len(), 510);
+        // The action still comes back when the reply doesn't fit
+        let app = App::new(Mapping::new());
+        let action = execute(Command::Select("zoom"), &app, &mut out);
+        assert_eq!(action, Ok(Some(Action::Select(2))));
+    }
+}
@@ -0,0 +1,22 @@
+use core::fmt;
+
+// Health of the device, written to the serial port whenever it changes
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Diagnostics {
+    pub display_up: bool,
+    pub i2c_errors: u32,
+    // False if the flash store wouldn't open, so nothing gets saved
+    pub store_up: bool,
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let display = if self.display_up { "up" } else { "down" };
+        let store = if self.store_up { "up" } else { "down" };
+        write!(
+            f,
+            "display {}, {} i2c errors, store {}",
+            display, self.i2c_errors, store
+        )
+    }
+}
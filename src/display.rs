@@ -0,0 +1,222 @@
+use heapless::{consts, String};
+
+// 128x32 SSD1306 with the 6x8 font
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 32;
+pub const ROWS: usize = 4;
+pub const COLS: usize = 21;
+
+// What the screen shows while the menu is closed
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Screen {
+    // The active pattern's debug lines
+    Debug,
+    // The LEDs drawn in place, see `preview`
+    Preview,
+}
+
+// Anything the menu and debug screens can draw on; the OLED on the board,
+// or a TextBuffer when testing on the host
+pub trait Canvas {
+    fn clear(&mut self);
+    // Text with its top left corner at x, y
+    fn text_at(&mut self, x: i16, y: i16, s: &str);
+    fn pixel(&mut self, x: u8, y: u8, on: bool);
+    fn text(&mut self, row: u8, s: &str) {
+        self.text_at(0, 8 * row as i16, s);
+    }
+}
+
+// In-memory canvas keeping what each row would show, and a bitmap of the
+// pixels drawn on top
+pub struct TextBuffer {
+    rows: [String<consts::U32>; ROWS],
+    // One bit per row of each column
+    columns: [u32; WIDTH],
+}
+
+impl TextBuffer {
+    pub fn new() -> Self {
+        let rows = [String::new(), String::new(), String::new(), String::new()];
+        let columns = [0; WIDTH];
+        Self { rows, columns }
+    }
+    pub fn row(&self, row: usize) -> &str {
+        self.rows.get(row).map_or("", |r| r.as_str())
+    }
+    pub fn is_on(&self, x: usize, y: usize) -> bool {
+        x < WIDTH && y < HEIGHT && self.columns[x] & (1 << y) != 0
+    }
+}
+
+impl Canvas for TextBuffer {
+    fn clear(&mut self) {
+        for r in self.rows.iter_mut() {
+            *r = String::new();
+        }
+        self.columns = [0; WIDTH];
+    }
+    // Only the row is kept, shifting by a pixel or two stays on it
+    fn text_at(&mut self, _x: i16, y: i16, s: &str) {
+        if y < 0 {
+            return;
+        }
+        if let Some(r) = self.rows.get_mut(y as usize / 8) {
+            *r = String::new();
+            // Anything past the edge of the screen is lost there too
+            for c in s.chars().take(COLS) {
+                let _ = r.push(c);
+            }
+        }
+    }
+    fn pixel(&mut self, x: u8, y: u8, on: bool) {
+        let (x, y) = (x as usize, y as usize);
+        if x < WIDTH && y < HEIGHT {
+            if on {
+                self.columns[x] |= 1 << y;
+            } else {
+                self.columns[x] &= !(1 << y);
+            }
+        }
+    }
+}
+
+// How lit the screen is after a while without input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    Awake,
+    Dim,
+    Off,
+}
+
+// Offsets stepped through against burn-in; text leaves two spare columns
+// and the font's bottom pixel row is nearly empty
+const SHIFTS: [(i8, i8); 6] = [(0, 0), (1, 0), (2, 0), (2, 1), (1, 1), (0, 1)];
+
+pub fn shift(step: u32) -> (i8, i8) {
+    SHIFTS[step as usize % SHIFTS.len()]
+}
+
+// Draws onto another canvas moved by a few pixels
+pub struct Shifted<'a, C: Canvas> {
+    canvas: &'a mut C,
+    x: i8,
+    y: i8,
+}
+
+impl<'a, C: Canvas> Shifted<'a, C> {
+    pub fn new(canvas: &'a mut C, (x, y): (i8, i8)) -> Self {
+        Self { canvas, x, y }
+    }
+}
+
+impl<'a, C: Canvas> Canvas for Shifted<'a, C> {
+    fn clear(&mut self) {
+        self.canvas.clear();
+    }
+    fn text_at(&mut self, x: i16, y: i16, s: &str) {
+        self.canvas.text_at(x + self.x as i16, y + self.y as i16, s);
+    }
+    fn pixel(&mut self, x: u8, y: u8, on: bool) {
+        let x = x as i16 + self.x as i16;
+        let y = y as i16 + self.y as i16;
+        if x >= 0 && y >= 0 && x < WIDTH as i16 && y < HEIGHT as i16 {
+            self.canvas.pixel(x as u8, y as u8, on);
+        }
+    }
+}
+
+// Turns off every other pixel in a checkerboard, halving the light
+pub fn dim(c: &mut impl Canvas) {
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            if (x + y) % 2 == 1 {
+                c.pixel(x as u8, y as u8, false);
+            }
+        }
+    }
+}
+
+// Longest wait between attempts to bring the display back, in calls to
+// `retry`
+const MAX_BACKOFF: u32 = 32;
+
+// Whether the display answered last time, so a missing or failing one is
+// retried with growing gaps instead of stopping the device
+pub struct Link {
+    up: bool,
+    errors: u32,
+    backoff: u32,
+    wait: u32,
+}
+
+impl Link {
+    pub const fn new() -> Self {
+        Self {
+            up: false,
+            errors: 0,
+            backoff: 1,
+            wait: 0,
+        }
+    }
+    pub fn is_up(&self) -> bool {
+        self.up
+    }
+    // Failed transfers so far
+    pub fn errors(&self) -> u32 {
+        self.errors
+    }
+    // Call on each screen update while down; true when it's time to
+    // initialise the display again
+    pub fn retry(&mut self) -> bool {
+        if self.up {
+            return false;
+        }
+        if self.wait > 0 {
+            self.wait -= 1;
+            return false;
+        }
+        true
+    }
+    // Result of a transfer; true if the link went up or down
+    pub fn done<E>(&mut self, r: Result<(), E>) -> bool {
+        let was_up = self.up;
+        match r {
+            Ok(()) => {
+                self.up = true;
+                self.backoff = 1;
+            }
+            Err(_) => {
+                self.up = false;
+                self.errors = self.errors.wrapping_add(1);
+                self.wait = self.backoff;
+                self.backoff = (self.backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+        was_up != self.up
+    }
+}
+
+#[cfg(feature = "device")]
+mod oled {
+    use super::Canvas;
+    use embedded_graphics::{fonts::Font6x8, prelude::*};
+    use ssd1306::{interface::DisplayInterface, mode::GraphicsMode};
+
+    impl<DI: DisplayInterface> Canvas for GraphicsMode<DI> {
+        fn clear(&mut self) {
+            GraphicsMode::clear(self);
+        }
+        fn text_at(&mut self, x: i16, y: i16, s: &str) {
+            self.draw(
+                Font6x8::render_str(s)
+                    .with_stroke(Some(1u8.into()))
+                    .translate(Coord::new(x as i32, y as i32))
+                    .into_iter(),
+            );
+        }
+        fn pixel(&mut self, x: u8, y: u8, on: bool) {
+            self.set_pixel(x as u32, y as u32, on as u8);
+        }
+    }
+}
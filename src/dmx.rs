@@ -0,0 +1,355 @@
+// DMX512 from a desk, through an RS-485 transceiver on a USART at 250
+// kbaud. A packet is a break, which the USART reports as a framing error,
+// then a start code and up to 512 slots. Only packets with the null start
+// code carry levels; the rest, e.g. RDM, are skipped.
+use crate::app::App;
+use crate::input::Action;
+use crate::m6::{Render, LEDS};
+use crate::preset::MAX_PARAMS;
+use crate::render::NAMES;
+
+pub const BAUD: u32 = 250_000;
+pub const SLOTS: usize = 512;
+// Most channels a personality takes
+pub const FOOTPRINT: usize = LEDS * 3;
+
+// What the channels from the start address do
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Personality {
+    // Pattern, brightness, then the active pattern's parameters
+    Control = 0,
+    // RGB for every LED, shown like any other stream
+    Direct = 1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    // Until the next break
+    Wait,
+    StartCode,
+    // Number of the next slot, from 1 like the addresses
+    Slots(usize),
+}
+
+// Keeps the channels of one fixture out of each packet
+pub struct Receiver {
+    state: State,
+    address: usize,
+    window: [u8; FOOTPRINT],
+}
+
+impl Receiver {
+    pub const fn new() -> Self {
+        Self {
+            state: State::Wait,
+            address: 1,
+            window: [0; FOOTPRINT],
+        }
+    }
+    // From 1; takes effect at the next packet
+    pub fn set_address(&mut self, address: u16) {
+        self.address = address as usize;
+    }
+    // The fixture's channels, complete when `push` or `line_break` returns
+    // true and overwritten by the next packet
+    pub fn window(&self) -> &[u8; FOOTPRINT] {
+        &self.window
+    }
+    // True when the last packet ended short of the fixture's last channel,
+    // which then reads 0
+    pub fn line_break(&mut self) -> bool {
+        let short = match self.state {
+            State::Slots(n) if n < self.address + FOOTPRINT => {
+                let got = n.saturating_sub(self.address);
+                for b in self.window[got..].iter_mut() {
+                    *b = 0;
+                }
+                true
+            }
+            _ => false,
+        };
+        self.state = State::StartCode;
+        short
+    }
+    // Lost bytes, e.g. an overrun; the packet is dropped
+    pub fn error(&mut self) {
+        self.state = State::Wait;
+    }
+    // True once the fixture's last channel has arrived
+    pub fn push(&mut self, b: u8) -> bool {
+        match self.state {
+            State::Wait => false,
+            State::StartCode => {
+                self.state = if b == 0 { State::Slots(1) } else { State::Wait };
+                false
+            }
+            // Past the last slot, so noise; the window still goes out at
+            // the next break
+            State::Slots(n) if n > SLOTS => false,
+            State::Slots(n) => {
+                self.state = State::Slots(n + 1);
+                match n.checked_sub(self.address) {
+                    Some(i) if i < FOOTPRINT => {
+                        self.window[i] = b;
+                        i == FOOTPRINT - 1
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+// Channels of the control personality
+const PATTERN: usize = 0;
+const BRIGHTNESS: usize = 1;
+const PARAMS: usize = 2;
+pub const CONTROL: usize = PARAMS + MAX_PARAMS;
+
+// Turns the control personality's channels into actions as they change,
+// since desks resend every level many times a second
+pub struct Control {
+    last: [Option<u8>; CONTROL],
+    // Whose parameters the last levels went to
+    pattern: usize,
+}
+
+impl Control {
+    pub const fn new() -> Self {
+        Self {
+            last: [None; CONTROL],
+            pattern: 0,
+        }
+    }
+    pub fn apply(&mut self, window: &[u8], app: &App) -> [Option<Action>; CONTROL] {
+        let mut actions = [None; CONTROL];
+        // Changed some other way, so the parameters are sent afresh
+        if app.patterns.active() != self.pattern {
+            self.pattern = app.patterns.active();
+            for last in self.last[PARAMS..].iter_mut() {
+                *last = None;
+            }
+        }
+        let changed = |ch: usize, last: &mut [Option<u8>]| {
+            let v = window[ch];
+            if last[ch] == Some(v) {
+                return None;
+            }
+            last[ch] = Some(v);
+            Some(v)
+        };
+        if let Some(v) = changed(PATTERN, &mut self.last) {
+            let n = v as usize * NAMES.len() / 256;
+            if n != app.patterns.active() {
+                // The parameters follow from the next packet, once the new
+                // pattern is active
+                actions[PATTERN] = Some(Action::Select(n as u8));
+                return actions;
+            }
+        }
+        if let Some(v) = changed(BRIGHTNESS, &mut self.last) {
+            actions[BRIGHTNESS] = Some(Action::Setting {
+                param: 0,
+                value: v as i16,
+            });
+        }
+        let params = app.patterns.params();
+        for (i, p) in params.iter().enumerate().take(MAX_PARAMS) {
+            if let Some(v) = changed(PARAMS + i, &mut self.last) {
+                // 0 to 255 across the whole range
+                let span = p.max as i32 - p.min as i32;
+                let value = p.min as i32 + (v as i32 * span + 127) / 255;
+                actions[PARAMS + i] = Some(Action::Set {
+                    param: i as u8,
+                    value: value as i16,
+                });
+            }
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::input::{InputEvent, Mapping};
+    use std::vec::Vec;
+
+    // Levels that tell the slots apart
+    fn universe() -> Vec<u8> {
+        (0..SLOTS).map(|i| (i % 251) as u8).collect()
+    }
+
+    // Each packet after a break, then the break after the last; returns
+    // the windows delivered
+    fn feed(r: &mut Receiver, packets: &[(u8, &[u8])]) -> Vec<[u8; FOOTPRINT]> {
+        let mut rv = Vec::new();
+        for &(start, slots) in packets {
+            if r.line_break() {
+                rv.push(*r.window());
+            }
+            for &b in [start].iter().chain(slots) {
+                if r.push(b) {
+                    rv.push(*r.window());
+                }
+            }
+        }
+        if r.line_break() {
+            rv.push(*r.window());
+        }
+        rv
+    }
+
+    #[test]
+    fn takes_the_window_at_the_address() {
+        let u = universe();
+        let mut r = Receiver::new();
+        // Nothing counts before the first break
+        for &b in &u[..20] {
+            assert!(!r.push(b));
+        }
+        let w = feed(&mut r, &[(0, &u)]);
+        assert_eq!(w.len(), 1);
+        assert_eq!(w[0][..], u[..FOOTPRINT]);
+        r.set_address(10);
+        let w = feed(&mut r, &[(0, &u)]);
+        assert_eq!(w.len(), 1);
+        assert_eq!(w[0][..], u[9..9 + FOOTPRINT]);
+    }
+
+    #[test]
+    fn skips_other_start_codes() {
+        let u = universe();
+        let mut r = Receiver::new();
+        // RDM, then levels
+        let w = feed(&mut r, &[(0xcc, &u), (0, &u[..FOOTPRINT])]);
+        assert_eq!(w.len(), 1);
+        assert_eq!(w[0][..], u[..FOOTPRINT]);
+        let w = feed(&mut r, &[(0x17, &u)]);
+        assert!(w.is_empty());
+    }
+
+    #[test]
+    fn short_packets_end_at_the_break() {
+        let u = universe();
+        let mut r = Receiver::new();
+        r.set_address(5);
+        let w = feed(&mut r, &[(0, &u[..20])]);
+        assert_eq!(w.len(), 1);
+        assert_eq!(w[0][..16], u[4..20]);
+        assert!(w[0][16..].iter().all(|&b| b == 0));
+        // Ending before the address leaves the fixture black
+        let w = feed(&mut r, &[(0, &u[..3])]);
+        assert_eq!(w.len(), 1);
+        assert!(w[0].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn window_past_the_last_slot() {
+        let u = universe();
+        let mut r = Receiver::new();
+        r.set_address(SLOTS as u16);
+        let w = feed(&mut r, &[(0, &u)]);
+        assert_eq!(w.len(), 1);
+        assert_eq!(w[0][0], u[SLOTS - 1]);
+        assert!(w[0][1..].iter().all(|&b| b == 0));
+        // Slots past 512 are noise, not levels
+        r.set_address(SLOTS as u16 - 1);
+        let mut long = u.clone();
+        long.extend_from_slice(&[0xff; FOOTPRINT]);
+        let w = feed(&mut r, &[(0, &long)]);
+        assert_eq!(w.len(), 1);
+        assert_eq!(w[0][..2], u[SLOTS - 2..]);
+        assert!(w[0][2..].iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn errors_drop_the_packet() {
+        let u = universe();
+        let mut r = Receiver::new();
+        assert!(!r.line_break());
+        assert!(!r.push(0));
+        assert!(!r.push(1));
+        // An overrun
+        r.error();
+        for &b in &u {
+            assert!(!r.push(b));
+        }
+        assert!(!r.line_break());
+        // Back to normal from the next break
+        let w = feed(&mut r, &[(0, &u)]);
+        assert_eq!(w.len(), 1);
+    }
+
+    fn run(app: &mut App, actions: &[Option<Action>]) {
+        for action in actions.iter().filter_map(|a| *a) {
+            app.input(InputEvent::Command(action));
+        }
+    }
+
+    #[test]
+    fn control_follows_changes() {
+        let mut app = App::new(Mapping::new());
+        let mut c = Control::new();
+        let mut w = [0; FOOTPRINT];
+        w[PATTERN] = 200;
+        w[BRIGHTNESS] = 128;
+        w[PARAMS] = 255;
+        // The pattern comes first, alone
+        let a = c.apply(&w, &app);
+        assert_eq!(a[PATTERN], Some(Action::Select(2)));
+        assert!(a[BRIGHTNESS..].iter().all(|a| a.is_none()));
+        run(&mut app, &a);
+        let a = c.apply(&w, &app);
+        assert_eq!(a[PATTERN], None);
+        let brightness = Action::Setting {
+            param: 0,
+            value: 128,
+        };
+        assert_eq!(a[BRIGHTNESS], Some(brightness));
+        // 0 and 255 are the ends of each parameter's range
+        let p = app.patterns.params();
+        let (first, second) = (p[0], p[1]);
+        assert_eq!(
+            a[PARAMS],
+            Some(Action::Set {
+                param: 0,
+                value: first.max
+            })
+        );
+        assert_eq!(
+            a[PARAMS + 1],
+            Some(Action::Set {
+                param: 1,
+                value: second.min
+            })
+        );
+        run(&mut app, &a);
+        assert_eq!(app.settings.brightness, 128);
+        // Levels resent unchanged do nothing
+        assert!(c.apply(&w, &app).iter().all(|a| a.is_none()));
+        w[BRIGHTNESS] = 0;
+        let a = c.apply(&w, &app);
+        assert_eq!(a.iter().filter(|a| a.is_some()).count(), 1);
+        assert_eq!(a[BRIGHTNESS], Some(Action::Setting { param: 0, value: 0 }));
+    }
+
+    #[test]
+    fn control_resends_after_a_pattern_change() {
+        let mut app = App::new(Mapping::new());
+        let mut c = Control::new();
+        let mut w = [0; FOOTPRINT];
+        w[PARAMS] = 10;
+        let a = c.apply(&w, &app);
+        run(&mut app, &a);
+        // Changed on the knobs: the desk's levels go to the new pattern,
+        // but it stays
+        app.input(InputEvent::Command(Action::Select(1)));
+        let a = c.apply(&w, &app);
+        assert_eq!(a[PATTERN], None);
+        assert_eq!(a[BRIGHTNESS], None);
+        assert!(a[PARAMS].is_some());
+    }
+}
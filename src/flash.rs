@@ -0,0 +1,114 @@
+// The store's two pages at the end of the 128K of flash in memory.x,
+// programmed through the flash interface's registers. The HAL only uses
+// ACR, for the wait states, so these are left to us; memory.x keeps the
+// program clear of the pages. Programming and erasing stall the core, as
+// it fetches from the same flash, so nothing else runs meanwhile: about
+// 40ms per erase.
+use core::ptr;
+
+use stm32f1xx_hal::pac::FLASH;
+
+use crate::store::FlashStorage;
+
+const BASE: usize = 0x0801_f800;
+const PAGE_SIZE: usize = 1024;
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xcdef_89ab;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    OutOfRange,
+    // Programming a half word that wasn't erased
+    Program,
+    WriteProtected,
+}
+
+pub struct Flash {
+    _private: (),
+}
+
+impl Flash {
+    pub fn new() -> Self {
+        Self { _private: () }
+    }
+    fn regs(&self) -> &'static stm32f1xx_hal::pac::flash::RegisterBlock {
+        unsafe { &*FLASH::ptr() }
+    }
+    fn check(&self, offset: usize, len: usize) -> Result<(), Error> {
+        if offset + len > 2 * PAGE_SIZE {
+            return Err(Error::OutOfRange);
+        }
+        Ok(())
+    }
+    fn unlock(&self) {
+        let regs = self.regs();
+        if regs.cr.read().lock().bit_is_set() {
+            regs.keyr.write(|w| unsafe { w.key().bits(KEY1) });
+            regs.keyr.write(|w| unsafe { w.key().bits(KEY2) });
+        }
+    }
+    // Waits out the operation, then locks the flash again and clears the
+    // status flags
+    fn finish(&self) -> Result<(), Error> {
+        let regs = self.regs();
+        while regs.sr.read().bsy().bit_is_set() {}
+        let sr = regs.sr.read();
+        regs.cr
+            .modify(|_r, w| w.pg().clear_bit().per().clear_bit().lock().set_bit());
+        regs.sr
+            .write(|w| w.eop().set_bit().pgerr().set_bit().wrprterr().set_bit());
+        if sr.wrprterr().bit_is_set() {
+            Err(Error::WriteProtected)
+        } else if sr.pgerr().bit_is_set() {
+            Err(Error::Program)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for Flash {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FlashStorage for Flash {
+    type Error = Error;
+    fn page_size(&self) -> usize {
+        PAGE_SIZE
+    }
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), Error> {
+        self.check(offset, buf.len())?;
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = unsafe { ptr::read_volatile((BASE + offset + i) as *const u8) };
+        }
+        Ok(())
+    }
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Error> {
+        self.check(offset, data.len())?;
+        self.unlock();
+        let regs = self.regs();
+        regs.cr.modify(|_r, w| w.pg().set_bit());
+        for (i, hw) in data.chunks(2).enumerate() {
+            let at = (BASE + offset + 2 * i) as *mut u16;
+            unsafe { ptr::write_volatile(at, u16::from_le_bytes([hw[0], hw[1]])) };
+            while regs.sr.read().bsy().bit_is_set() {}
+            let sr = regs.sr.read();
+            if sr.pgerr().bit_is_set() || sr.wrprterr().bit_is_set() {
+                break;
+            }
+        }
+        self.finish()
+    }
+    fn erase(&mut self, page: usize) -> Result<(), Error> {
+        self.check(page * PAGE_SIZE, PAGE_SIZE)?;
+        self.unlock();
+        let regs = self.regs();
+        regs.cr.modify(|_r, w| w.per().set_bit());
+        regs.ar
+            .write(|w| unsafe { w.far().bits((BASE + page * PAGE_SIZE) as u32) });
+        regs.cr.modify(|_r, w| w.strt().set_bit());
+        self.finish()
+    }
+}
@@ -0,0 +1,117 @@
+use smart_leds::RGB8;
+
+use crate::hsv::{HSV, HUE_STEPS};
+use crate::palette::{blend, Palette};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Harmony {
+    Complementary,
+    Analogous,
+    Triadic,
+    Tetradic,
+    SplitComplementary,
+}
+
+// In the order the "palette" parameter offers them, after the fixed palettes
+pub const HARMONIES: [Harmony; 5] = [
+    Harmony::Complementary,
+    Harmony::Analogous,
+    Harmony::Triadic,
+    Harmony::Tetradic,
+    Harmony::SplitComplementary,
+];
+
+impl Harmony {
+    // Hue offsets from the seed, in degrees
+    pub fn offsets(&self) -> &'static [i16] {
+        use Harmony::*;
+        match self {
+            Complementary => &[0, 180],
+            Analogous => &[-30, 0, 30],
+            Triadic => &[0, 120, 240],
+            Tetradic => &[0, 60, 180, 240],
+            SplitComplementary => &[0, 150, 210],
+        }
+    }
+    pub fn colors(&self, seed: &HSV) -> impl Iterator<Item = HSV> + '_ {
+        let seed = *seed;
+        self.offsets()
+            .iter()
+            .map(move |deg| seed.shifted_hue(degrees(*deg)))
+    }
+    // Spread the scheme around a 16 entry palette, blending between
+    // neighbouring colors so sampling cycles smoothly through the scheme
+    pub fn palette(&self, seed: &HSV) -> Palette {
+        let mut colors = [RGB8::default(); 4];
+        let mut n = 0;
+        for (slot, c) in colors.iter_mut().zip(self.colors(seed)) {
+            *slot = c.to_rgb_raw().into();
+            n += 1;
+        }
+        let mut entries = [RGB8::default(); 16];
+        for (i, e) in entries.iter_mut().enumerate() {
+            let pos = (i * n * 256) / 16;
+            let k = pos >> 8;
+            *e = blend(colors[k], colors[(k + 1) % n], (pos & 0xff) as u8);
+        }
+        Palette::Sixteen(entries)
+    }
+}
+
+fn degrees(deg: i16) -> i16 {
+    ((deg as i32 * HUE_STEPS as i32) / 360) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    fn hues(h: Harmony, seed: &HSV) -> Vec<u16> {
+        h.colors(seed).map(|c| c.h).collect()
+    }
+
+    #[test]
+    fn offsets_around_the_wheel() {
+        let red = HSV::new(0, 255, 255);
+        assert_eq!(hues(Harmony::Complementary, &red), [0, 768]);
+        assert_eq!(hues(Harmony::Analogous, &red), [1408, 0, 128]);
+        assert_eq!(hues(Harmony::Triadic, &red), [0, 512, 1024]);
+        assert_eq!(hues(Harmony::Tetradic, &red), [0, 256, 768, 1024]);
+        assert_eq!(hues(Harmony::SplitComplementary, &red), [0, 640, 896]);
+        // From any seed, keeping its saturation and value
+        let seed = HSV::new(1400, 100, 50);
+        assert_eq!(hues(Harmony::Triadic, &seed), [1400, 376, 888]);
+        assert!(Harmony::Triadic
+            .colors(&seed)
+            .all(|c| c.s == 100 && c.v == 50));
+    }
+
+    #[test]
+    fn palettes_pass_through_each_color() {
+        let seed = HSV::new(200, 255, 255);
+        for &h in &HARMONIES {
+            let colors: Vec<RGB8> = h.colors(&seed).map(|c| c.to_rgb_raw().into()).collect();
+            let n = colors.len();
+            let entries = match h.palette(&seed) {
+                Palette::Sixteen(entries) => entries,
+                p => panic!("{:?}: {:?}", h, p),
+            };
+            // Each color lands on an entry wherever 16 divides evenly,
+            // always the first at the start
+            for (k, &c) in colors.iter().enumerate() {
+                if k * 16 % n == 0 {
+                    assert_eq!(entries[k * 16 / n], c, "{:?} {}", h, k);
+                }
+            }
+            // and the last entry leads back round to it
+            let last = entries[15];
+            let (a, b) = (colors[n - 1], colors[0]);
+            let between = |x: u8, y: u8, z: u8| x.min(z) <= y && y <= x.max(z);
+            assert!(between(a.r, last.r, b.r) && between(a.g, last.g, b.g));
+            assert!(between(a.b, last.b, b.b), "{:?}", h);
+        }
+    }
+}
@@ -1,7 +1,34 @@
 use core::mem::swap;
 use smart_leds::RGB8;
 
-pub const HUE_MAX: i16 = (256 * 6) - 1;
+// Hue resolution: steps per sextant of the spectrum wheel and in total
+pub const HUE_SEXTANT: i16 = 256;
+pub const HUE_STEPS: i16 = HUE_SEXTANT * 6;
+pub const HUE_MAX: i16 = HUE_STEPS - 1;
+
+// Numbered as the patterns' "hue map" parameter
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HueMap {
+    // Six equal sextants, yellow/cyan/magenta are narrow bands
+    Spectrum = 0,
+    // FastLED's visually balanced eight section mapping, wider yellow/orange
+    Rainbow = 1,
+}
+
+impl HueMap {
+    pub fn numbered(n: i16) -> Self {
+        match n {
+            1 => HueMap::Rainbow,
+            _ => HueMap::Spectrum,
+        }
+    }
+}
+
+impl Default for HueMap {
+    fn default() -> Self {
+        HueMap::Spectrum
+    }
+}
 
 pub const GAMMA: [u8; 256] = [
     0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1,
@@ -26,11 +53,78 @@ pub struct HSV {
 
 impl HSV {
     pub const fn new(h: i16, s: u8, v: u8) -> Self {
-        let h: u16 = (((h % HUE_MAX) + HUE_MAX) % HUE_MAX) as u16;
+        let h: u16 = (((h % HUE_STEPS) + HUE_STEPS) % HUE_STEPS) as u16;
         Self { h, s, v }
     }
-    // From http://www.vagrearg.org/content/hsvrgb
+    // Inverse of to_rgb_raw, using the spectrum mapping
+    pub fn from_rgb(r: u8, g: u8, b: u8) -> Self {
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = (max - min) as i32;
+        if delta == 0 {
+            return Self { h: 0, s: 0, v: max };
+        }
+        let s = ((delta * 255) / max as i32) as u8;
+        let (r, g, b) = (r as i32, g as i32, b as i32);
+        let sextant = HUE_SEXTANT as i32;
+        let h = if max as i32 == r {
+            (sextant * (g - b)) / delta
+        } else if max as i32 == g {
+            (sextant * 2) + (sextant * (b - r)) / delta
+        } else {
+            (sextant * 4) + (sextant * (r - g)) / delta
+        };
+        Self::new(h as i16, s, max)
+    }
     pub fn to_rgb(&self) -> (u8, u8, u8) {
+        self.to_rgb_mapped(HueMap::Spectrum)
+    }
+    pub fn to_rgb_mapped(&self, map: HueMap) -> (u8, u8, u8) {
+        let (r, g, b) = self.to_rgb_raw_mapped(map);
+        (GAMMA[r as usize], GAMMA[g as usize], GAMMA[b as usize])
+    }
+    pub fn to_rgb_raw_mapped(&self, map: HueMap) -> (u8, u8, u8) {
+        match map {
+            HueMap::Spectrum => self.to_rgb_raw(),
+            HueMap::Rainbow => self.to_rainbow_raw(),
+        }
+    }
+    // After FastLED's hsv2rgb_rainbow, spread over HUE_STEPS instead of 256
+    pub fn to_rainbow_raw(&self) -> (u8, u8, u8) {
+        let &Self { h, s, v } = self;
+        let section_len = (HUE_STEPS / 8) as u32;
+        let section = h as u32 / section_len;
+        let offset = h as u32 % section_len;
+        let third = (offset * 85 / section_len) as u8;
+        let twothirds = (offset * 170 / section_len) as u8;
+        let (r, g, b): (u8, u8, u8) = match section {
+            // red -> orange
+            0 => (255 - third, third, 0),
+            // orange -> yellow
+            1 => (171, 85 + third, 0),
+            // yellow -> green
+            2 => (171 - twothirds, 170 + third, 0),
+            // green -> aqua
+            3 => (0, 255 - third, third),
+            // aqua -> blue
+            4 => (0, 171 - twothirds, 85 + twothirds),
+            // blue -> purple
+            5 => (third, 0, 255 - third),
+            // purple -> pink
+            6 => (85 + third, 0, 171 - third),
+            // pink -> red
+            _ => (170 + third, 0, 85 - third),
+        };
+        let scale = |x: u8| -> u8 {
+            let floor = 255 - s as u16;
+            let x = ((x as u16 * s as u16) / 255) + floor;
+            ((x * v as u16) / 255) as u8
+        };
+        (scale(r), scale(g), scale(b))
+    }
+    // From http://www.vagrearg.org/content/hsvrgb
+    // Without gamma correction, for blending before output
+    pub fn to_rgb_raw(&self) -> (u8, u8, u8) {
         let &Self { h, s, v } = self;
         if s == 0 {
             return (v, v, v);
@@ -73,17 +167,16 @@ impl HSV {
         d += d >> 8;
         d += v as u32;
         *pr = (d >> 16) as u8;
-        //(r, g, b)
-        (GAMMA[r as usize], GAMMA[g as usize], GAMMA[b as usize])
+        (r, g, b)
     }
     pub fn shift_hue(&mut self, d: i16) {
         let mut hue = self.h as i16 + d;
         if d.is_negative() {
             while hue < 0 {
-                hue += HUE_MAX;
+                hue += HUE_STEPS;
             }
         } else {
-            hue %= HUE_MAX;
+            hue %= HUE_STEPS;
         }
         self.h = hue as u16;
     }
@@ -105,3 +198,41 @@ impl Into<RGB8> for &HSV {
         self.to_rgb().into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rainbow(h: i16) -> (u8, u8, u8) {
+        HSV::new(h, 255, 255).to_rainbow_raw()
+    }
+
+    #[test]
+    fn rainbow_across_the_sextants() {
+        // Eight sections of 192 steps, so the spectrum's sextant
+        // boundaries fall inside them
+        assert_eq!(rainbow(0), (255, 0, 0));
+        assert_eq!(rainbow(191), (171, 84, 0));
+        assert_eq!(rainbow(192), (171, 85, 0));
+        assert_eq!(rainbow(255), (171, 112, 0));
+        assert_eq!(rainbow(256), (171, 113, 0));
+        assert_eq!(rainbow(HUE_MAX), (254, 0, 1));
+        // A full turn comes back to red
+        assert_eq!(rainbow(HUE_STEPS), rainbow(0));
+        assert_eq!(rainbow(-1), rainbow(HUE_MAX));
+    }
+
+    #[test]
+    fn rainbow_scales_by_saturation_and_value() {
+        assert_eq!(HSV::new(256, 0, 200).to_rainbow_raw(), (200, 200, 200));
+        assert_eq!(HSV::new(0, 255, 128).to_rainbow_raw(), (128, 0, 0));
+        assert_eq!(HSV::new(0, 128, 255).to_rainbow_raw(), (255, 127, 127));
+    }
+
+    #[test]
+    fn hue_maps_by_number() {
+        assert_eq!(HueMap::numbered(HueMap::Rainbow as i16), HueMap::Rainbow);
+        assert_eq!(HueMap::numbered(0), HueMap::Spectrum);
+        assert_eq!(HueMap::numbered(7), HueMap::Spectrum);
+    }
+}
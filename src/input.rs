@@ -0,0 +1,114 @@
+use crate::button::Gesture;
+
+// Number of knobs/buttons the mapping has room for
+pub const MAX_INPUTS: usize = 4;
+// Knob slots in each bank of pattern parameters
+pub const BANK_SIZE: usize = 2;
+
+// Raw events from whichever device produced them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    // `shift` is set while the mapping's shift button is held
+    Rotate { id: u8, delta: i16, shift: bool },
+    Button { id: u8, gesture: Gesture },
+    // Already resolved to an action, e.g. from the serial port
+    Command(Action),
+}
+
+// What an event means to the active pattern
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Adjust { param: u8, delta: i16 },
+    // A knob slot in a bank, resolved to a parameter by the pattern
+    Turn { bank: u8, slot: u8, delta: i16 },
+    Set { param: u8, value: i16 },
+    // One of the global settings rather than a pattern parameter
+    Setting { param: u8, value: i16 },
+    AdjustSetting { param: u8, delta: i16 },
+    Select(u8),
+    // Store or recall the active pattern and its parameters
+    Save(u8),
+    Load(u8),
+    NextPattern,
+    PrevPattern,
+    // Handled by the firmware rather than the patterns: write the input
+    // recording to the serial port
+    Dump,
+    // Open the on-screen menu, which then takes the knobs and buttons
+    Menu,
+    // Turn the LEDs off or back on
+    Power,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ButtonMap {
+    pub click: Option<Action>,
+    pub double_click: Option<Action>,
+    pub long_press: Option<Action>,
+}
+
+impl ButtonMap {
+    pub const fn none() -> Self {
+        Self {
+            click: None,
+            double_click: None,
+            long_press: None,
+        }
+    }
+}
+
+pub struct Mapping {
+    // Bank slot addressed by each knob
+    pub rotate: [Option<u8>; MAX_INPUTS],
+    pub buttons: [ButtonMap; MAX_INPUTS],
+    // Button switching the knobs to the second bank while held
+    pub shift: Option<u8>,
+}
+
+impl Mapping {
+    // Knobs address the bank slots in order, the first button steps
+    // through the patterns and shifts the knobs while held, a click on the
+    // second opens the menu and a long press dumps the input recording
+    pub const fn new() -> Self {
+        let rotate = [Some(0), Some(1), None, None];
+        let buttons = [
+            ButtonMap {
+                click: Some(Action::NextPattern),
+                double_click: Some(Action::PrevPattern),
+                long_press: None,
+            },
+            ButtonMap {
+                click: Some(Action::Menu),
+                double_click: None,
+                long_press: Some(Action::Dump),
+            },
+            ButtonMap::none(),
+            ButtonMap::none(),
+        ];
+        let shift = Some(0);
+        Self {
+            rotate,
+            buttons,
+            shift,
+        }
+    }
+    pub fn map(&self, ev: InputEvent) -> Option<Action> {
+        match ev {
+            InputEvent::Rotate { id, delta, shift } => {
+                let slot = (*self.rotate.get(id as usize)?)?;
+                let bank = shift as u8;
+                Some(Action::Turn { bank, slot, delta })
+            }
+            InputEvent::Button { id, gesture } => {
+                let b = self.buttons.get(id as usize)?;
+                match gesture {
+                    Gesture::Click => b.click,
+                    Gesture::DoubleClick => b.double_click,
+                    Gesture::LongPress => b.long_press,
+                    Gesture::HoldTurn(_) => None,
+                }
+            }
+            InputEvent::Command(action) => Some(action),
+        }
+    }
+}
@@ -0,0 +1,415 @@
+// Infrared remotes, decoded from the lengths of the marks (carrier on) and
+// spaces a demodulating receiver reports, see `capture` for timing them.
+// NEC and RC5 are decoded side by side, whichever the remote speaks.
+use crate::input::Action;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Nec,
+    Rc5,
+}
+
+// A key; NEC addresses are 16 bits when the remote sends the extended form
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Code {
+    pub protocol: Protocol,
+    pub address: u16,
+    pub command: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Key {
+    pub code: Code,
+    // Sent again while the key is held
+    pub repeat: bool,
+}
+
+// Within a quarter either way of `nominal`
+fn near(us: u32, nominal: u32) -> bool {
+    us >= nominal - nominal / 4 && us <= nominal + nominal / 4
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Nec {
+    Idle,
+    Leader,
+    // Bits so far and their count; the mark before each bit's space
+    Mark(u32, u32),
+    Space(u32, u32),
+    RepeatMark,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Rc5 {
+    Idle,
+    // Half bits so far, from the middle of the first start bit, and the
+    // bits, each decided by its first half
+    Bits(u32, u16),
+}
+
+// RC5 half bit
+const HALF: u32 = 889;
+const RC5_BITS: u32 = 14;
+
+pub struct Decoder {
+    nec: Nec,
+    rc5: Rc5,
+    // For NEC repeats, which don't repeat the code, and the RC5 toggle bit
+    last: Option<Code>,
+    toggle: Option<bool>,
+}
+
+impl Decoder {
+    pub const fn new() -> Self {
+        Self {
+            nec: Nec::Idle,
+            rc5: Rc5::Idle,
+            last: None,
+            toggle: None,
+        }
+    }
+    // Call at every edge with the level that just ended and its length
+    pub fn push(&mut self, mark: bool, us: u32) -> Option<Key> {
+        let nec = self.nec(mark, us);
+        let rc5 = self.rc5(mark, us);
+        nec.or(rc5)
+    }
+    fn nec(&mut self, mark: bool, us: u32) -> Option<Key> {
+        let (state, key) = match (self.nec, mark) {
+            (Nec::Leader, false) if near(us, 4500) => (Nec::Mark(0, 0), None),
+            (Nec::Leader, false) if near(us, 2250) => (Nec::RepeatMark, None),
+            (Nec::Mark(n, bits), true) if near(us, 560) => (Nec::Space(n, bits), None),
+            (Nec::Space(n, bits), false) if near(us, 560) || near(us, 1690) => {
+                let bits = bits | ((us > 1125) as u32) << n;
+                if n + 1 < 32 {
+                    (Nec::Mark(n + 1, bits), None)
+                } else {
+                    (Nec::Idle, self.nec_code(bits))
+                }
+            }
+            (Nec::RepeatMark, true) if near(us, 560) => {
+                let key = self
+                    .last
+                    .filter(|c| c.protocol == Protocol::Nec)
+                    .map(|code| Key { code, repeat: true });
+                (Nec::Idle, key)
+            }
+            // Anything unexpected starts over, perhaps with a new leader
+            (_, true) if near(us, 9000) => (Nec::Leader, None),
+            _ => (Nec::Idle, None),
+        };
+        self.nec = state;
+        key
+    }
+    // Least significant bit first: address, its inverse or the high byte
+    // of an extended address, command and its inverse
+    fn nec_code(&mut self, bits: u32) -> Option<Key> {
+        let [lo, hi, command, inverse] = bits.to_le_bytes();
+        if command != !inverse {
+            return None;
+        }
+        let address = if hi == !lo {
+            lo as u16
+        } else {
+            u16::from_le_bytes([lo, hi])
+        };
+        let code = Code {
+            protocol: Protocol::Nec,
+            address,
+            command,
+        };
+        self.last = Some(code);
+        Some(Key {
+            code,
+            repeat: false,
+        })
+    }
+    // Manchester coded, a 1 being a space then a mark. The idle space hides
+    // the first half of the first start bit, so a frame starts with a mark.
+    fn rc5(&mut self, mark: bool, us: u32) -> Option<Key> {
+        let halves = if near(us, HALF) {
+            1
+        } else if near(us, 2 * HALF) {
+            2
+        } else {
+            0
+        };
+        let (mut pos, mut bits) = match self.rc5 {
+            Rc5::Idle if mark && halves > 0 => (1, 1),
+            Rc5::Bits(pos, bits) => (pos, bits),
+            _ => return None,
+        };
+        // Two alike halves in a row only happen across a bit boundary
+        if halves == 0 || (halves == 2 && pos % 2 == 0) {
+            self.rc5 = Rc5::Idle;
+            return None;
+        }
+        for _ in 0..halves {
+            if pos % 2 == 0 {
+                bits = bits << 1 | !mark as u16;
+            }
+            pos += 1;
+        }
+        if pos < 2 * RC5_BITS - 1 {
+            self.rc5 = Rc5::Bits(pos, bits);
+            return None;
+        }
+        self.rc5 = Rc5::Idle;
+        self.rc5_code(bits)
+    }
+    // Start bits, toggle, five address and six command bits, most
+    // significant first; the second start bit is the inverted seventh
+    // command bit in extended RC5
+    fn rc5_code(&mut self, bits: u16) -> Option<Key> {
+        let toggle = bits & 0x800 != 0;
+        let code = Code {
+            protocol: Protocol::Rc5,
+            address: (bits >> 6) & 0x1f,
+            command: (bits & 0x3f) as u8 | (((bits >> 6) & 0x40) as u8 ^ 0x40),
+        };
+        let repeat = self.last == Some(code) && self.toggle == Some(toggle);
+        self.last = Some(code);
+        self.toggle = Some(toggle);
+        Some(Key { code, repeat })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Binding {
+    pub code: Code,
+    pub action: Action,
+    // Also on repeats, for keys that are held to step
+    pub repeat: bool,
+}
+
+// Room for this many keys in a keymap
+pub const KEYS: usize = 12;
+
+const fn nec(command: u8, action: Action, repeat: bool) -> Option<Binding> {
+    let code = Code {
+        protocol: Protocol::Nec,
+        address: 0,
+        command,
+    };
+    Some(Binding {
+        code,
+        action,
+        repeat,
+    })
+}
+
+const fn rc5(command: u8, action: Action, repeat: bool) -> Option<Binding> {
+    let code = Code {
+        protocol: Protocol::Rc5,
+        address: 0,
+        command,
+    };
+    Some(Binding {
+        code,
+        action,
+        repeat,
+    })
+}
+
+const BRIGHTER: Action = Action::AdjustSetting { param: 0, delta: 1 };
+const DIMMER: Action = Action::AdjustSetting {
+    param: 0,
+    delta: -1,
+};
+
+pub struct Keymap {
+    pub keys: [Option<Binding>; KEYS],
+}
+
+impl Keymap {
+    // The common 21 key NEC remote sold with receiver kits, and an RC5 TV
+    // remote
+    pub const fn new() -> Self {
+        Self {
+            keys: [
+                // Next, previous, play and volume up and down
+                nec(0x40, Action::NextPattern, false),
+                nec(0x44, Action::PrevPattern, false),
+                nec(0x43, Action::Power, false),
+                nec(0x15, BRIGHTER, true),
+                nec(0x07, DIMMER, true),
+                // Channel up and down, standby and volume up and down
+                rc5(32, Action::NextPattern, false),
+                rc5(33, Action::PrevPattern, false),
+                rc5(12, Action::Power, false),
+                rc5(16, BRIGHTER, true),
+                rc5(17, DIMMER, true),
+                None,
+                None,
+            ],
+        }
+    }
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.keys
+            .iter()
+            .filter_map(|b| b.as_ref())
+            .find(|b| b.code == key.code && (b.repeat || !key.repeat))
+            .map(|b| b.action)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec;
+    use std::vec::Vec;
+
+    // Levels and their lengths as a receiver reports them, which stretches
+    // marks a little
+    type Runs = Vec<(bool, u32)>;
+
+    fn nec_bits(bits: u32) -> Runs {
+        let mut v = vec![(false, 100_000), (true, 9050), (false, 4420)];
+        for i in 0..32 {
+            v.push((true, 620));
+            v.push((false, if bits >> i & 1 == 1 { 1630 } else { 500 }));
+        }
+        v.push((true, 600));
+        v
+    }
+
+    fn nec(address: u8, command: u8) -> Runs {
+        nec_bits(u32::from_le_bytes([address, !address, command, !command]))
+    }
+
+    fn nec_repeat() -> Runs {
+        vec![(false, 40_000), (true, 9000), (false, 2200), (true, 610)]
+    }
+
+    // Start bits, toggle, address and command, the second start bit being
+    // the inverted seventh command bit
+    fn rc5(toggle: bool, address: u16, command: u8) -> Runs {
+        let s2 = command & 0x40 == 0;
+        let bits = 1 << 13
+            | (s2 as u16) << 12
+            | (toggle as u16) << 11
+            | (address & 0x1f) << 6
+            | (command & 0x3f) as u16;
+        // A 1 is a space then a mark
+        let mut halves = Vec::new();
+        for i in (0..14).rev() {
+            let one = bits >> i & 1 == 1;
+            halves.extend_from_slice(&[!one, one]);
+        }
+        // The first half goes unseen in the idle space
+        let mut runs = vec![(false, 100_000)];
+        for &h in &halves[1..] {
+            match runs.last_mut() {
+                Some(r) if r.0 == h && r.1 < 50_000 => r.1 += HALF,
+                _ => runs.push((h, HALF)),
+            }
+        }
+        // Some jitter
+        for (i, r) in runs.iter_mut().enumerate().skip(1) {
+            r.1 = if r.0 { r.1 + 60 } else { r.1 - 40 } + (i as u32 % 3) * 30;
+        }
+        runs.push((false, 100_000));
+        runs
+    }
+
+    fn decode(d: &mut Decoder, runs: &[(bool, u32)]) -> Vec<Key> {
+        runs.iter().filter_map(|&(m, us)| d.push(m, us)).collect()
+    }
+
+    fn key(protocol: Protocol, address: u16, command: u8, repeat: bool) -> Key {
+        let code = Code {
+            protocol,
+            address,
+            command,
+        };
+        Key { code, repeat }
+    }
+
+    #[test]
+    fn nec_frames_and_repeats() {
+        let mut d = Decoder::new();
+        let k = decode(&mut d, &nec(0, 0x40));
+        assert_eq!(k, [key(Protocol::Nec, 0, 0x40, false)]);
+        let k = decode(&mut d, &[nec_repeat(), nec_repeat()].concat());
+        assert_eq!(k, [key(Protocol::Nec, 0, 0x40, true); 2]);
+        // The command's inverse doesn't match
+        let bits = u32::from_le_bytes([0, 0xff, 0x40, 0x40]);
+        assert!(decode(&mut d, &nec_bits(bits)).is_empty());
+    }
+
+    #[test]
+    fn extended_nec_addresses() {
+        let mut d = Decoder::new();
+        let bits = u32::from_le_bytes([0x12, 0x34, 9, !9]);
+        let k = decode(&mut d, &nec_bits(bits));
+        assert_eq!(k, [key(Protocol::Nec, 0x3412, 9, false)]);
+    }
+
+    #[test]
+    fn rc5_toggle_makes_a_new_press() {
+        let mut d = Decoder::new();
+        let runs = [
+            rc5(false, 0, 16),
+            rc5(false, 0, 16),
+            rc5(true, 0, 16),
+            rc5(true, 0, 12),
+        ]
+        .concat();
+        let repeats: Vec<bool> = decode(&mut d, &runs).iter().map(|k| k.repeat).collect();
+        assert_eq!(repeats, [false, true, false, false]);
+    }
+
+    #[test]
+    fn rc5_field_bit_extends_the_command() {
+        let mut d = Decoder::new();
+        for &(toggle, address, command) in &[(false, 0, 12), (true, 5, 0x3f), (false, 31, 0x45)] {
+            let k = decode(&mut d, &rc5(toggle, address, command));
+            assert_eq!(k, [key(Protocol::Rc5, address, command, false)]);
+        }
+    }
+
+    #[test]
+    fn noise_starts_over() {
+        let mut d = Decoder::new();
+        // A glitch in the middle of a frame loses it
+        let mut runs = nec(0, 0x15);
+        runs.insert(30, (true, 80));
+        assert!(decode(&mut d, &runs).is_empty());
+        // A repeat with nothing to repeat
+        assert!(decode(&mut d, &nec_repeat()).is_empty());
+        // Half a frame, then a whole one from its leader
+        let runs = nec(0, 0x07);
+        let k = decode(&mut d, &[&runs[..20], &runs[..]].concat());
+        assert_eq!(k, [key(Protocol::Nec, 0, 0x07, false)]);
+        // Sunlight flicker and remotes of other kinds in between
+        let noise = [(true, 200), (false, 3000), (true, 1500), (false, 300)];
+        let runs = [&noise[..], &rc5(false, 0, 33), &noise, &nec(0, 0x44)].concat();
+        let k = decode(&mut d, &runs);
+        assert_eq!(
+            k,
+            [
+                key(Protocol::Rc5, 0, 33, false),
+                key(Protocol::Nec, 0, 0x44, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn keymap_binds_held_keys_only_where_asked() {
+        let km = Keymap::new();
+        let next = key(Protocol::Nec, 0, 0x40, false);
+        assert_eq!(km.action(next), Some(Action::NextPattern));
+        assert_eq!(
+            km.action(Key {
+                repeat: true,
+                ..next
+            }),
+            None
+        );
+        let brighter = key(Protocol::Rc5, 0, 16, true);
+        assert_eq!(km.action(brighter), Some(BRIGHTER));
+        assert_eq!(km.action(key(Protocol::Rc5, 1, 16, false)), None);
+    }
+}
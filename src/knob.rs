@@ -1,40 +1,386 @@
 use embedded_hal::digital::v2::InputPin;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Direction {
     CW,
     CCW,
 }
 
+impl Direction {
+    pub fn sign(&self) -> i16 {
+        match self {
+            Direction::CW => 1,
+            Direction::CCW => -1,
+        }
+    }
+}
+
+// Gray code transitions per physical detent of the encoder
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Detent {
+    One,
+    Two,
+    Four,
+}
+
+impl Detent {
+    pub fn transitions(&self) -> i8 {
+        use Detent::*;
+        match self {
+            One => 1,
+            Two => 2,
+            Four => 4,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Curve {
+    Linear,
+    Quadratic,
+}
+
+// Detents closer together than `window` timer ticks are multiplied, up to
+// `max` as the interval approaches zero
+#[derive(Clone, Copy, Debug)]
+pub struct Acceleration {
+    pub window: u32,
+    pub max: u16,
+    pub curve: Curve,
+}
+
+impl Acceleration {
+    pub fn scale(&self, interval: u32) -> i16 {
+        if interval >= self.window || self.max <= 1 {
+            return 1;
+        }
+        // Speed within the window, 0..=256
+        let speed = (((self.window - interval) as u64 * 256) / self.window as u64) as u32;
+        let speed = match self.curve {
+            Curve::Linear => speed,
+            Curve::Quadratic => (speed * speed) >> 8,
+        };
+        (1 + (((self.max as u32 - 1) * speed) >> 8)) as i16
+    }
+}
+
+// Tracks the last step to scale the next one by turning speed
+struct Velocity {
+    accel: Option<Acceleration>,
+    last_step: Option<(u32, Direction)>,
+}
+
+impl Velocity {
+    const fn new() -> Self {
+        Self {
+            accel: None,
+            last_step: None,
+        }
+    }
+    fn scale(&mut self, now: u32, dir: Direction) -> i16 {
+        let scale = match (self.accel, self.last_step) {
+            (Some(accel), Some((t, last_dir))) if last_dir == dir => {
+                accel.scale(now.wrapping_sub(t))
+            }
+            _ => 1,
+        };
+        self.last_step = Some((now, dir));
+        scale
+    }
+}
+
+pub trait Encoder {
+    // Signed step count since the last poll, scaled by how quickly the knob
+    // is turning; `now` is any free running tick counter
+    fn poll_at(&mut self, now: u32) -> Option<i16>;
+    fn set_acceleration(&mut self, accel: Option<Acceleration>);
+    // Steps known to have been lost
+    fn errors(&self) -> u32 {
+        0
+    }
+}
+
+// Marks a transition where both bits changed at once
+const INVALID: i8 = 2;
+
+// Indexed by (last << 2) | next, each state being (a << 1) | b
+#[rustfmt::skip]
+const TRANSITIONS: [i8; 16] = [
+//  next: 00       01       10       11
+    0,       1,       -1,      INVALID, // last 00
+    -1,      0,       INVALID, 1,       // last 01
+    1,       INVALID, 0,       -1,      // last 10
+    INVALID, -1,      1,       0,       // last 11
+];
+
+fn read<A: InputPin, B: InputPin>(a: &A, b: &B) -> u8 {
+    ((a.is_high().unwrap_or(false) as u8) << 1) | (b.is_high().unwrap_or(false) as u8)
+}
+
 pub struct Knob<A: InputPin, B: InputPin> {
     a: A,
     b: B,
-    last: (bool, bool),
+    last: u8,
+    detent: Detent,
+    count: i8,
+    errors: u32,
+    velocity: Velocity,
 }
 
 impl<A: InputPin, B: InputPin> Knob<A, B> {
     pub fn new(a: A, b: B) -> Self {
-        let last = (false, false);
-        Self { a, b, last }
+        Self::with_detent(a, b, Detent::One)
+    }
+    pub fn with_detent(a: A, b: B, detent: Detent) -> Self {
+        // Wherever the knob rests, so the first poll isn't a transition
+        let last = read(&a, &b);
+        let count = 0;
+        let errors = 0;
+        let velocity = Velocity::new();
+        Self {
+            a,
+            b,
+            last,
+            detent,
+            count,
+            errors,
+            velocity,
+        }
     }
     #[inline(never)]
     pub fn poll(&mut self) -> Option<Direction> {
-        let next: (bool, bool) = (
-            self.a.is_high().unwrap_or(false),
-            self.b.is_high().unwrap_or(false),
-        );
+        let next = read(&self.a, &self.b);
         let last = self.last;
         self.last = next;
-        use Direction::*;
-        match (last, next) {
-            ((false, false), (false, true)) => Some(CW),
-            ((false, true), (true, true)) => Some(CW),
-            ((true, true), (true, false)) => Some(CW),
-            ((true, false), (false, false)) => Some(CW),
-            ((true, false), (true, true)) => Some(CCW),
-            ((true, true), (false, true)) => Some(CCW),
-            ((false, true), (false, false)) => Some(CCW),
-            ((false, false), (true, false)) => Some(CCW),
-            _ => None,
+        match TRANSITIONS[((last << 2) | next) as usize] {
+            0 => None,
+            INVALID => {
+                // Direction is unknown, so drop any partial detent
+                self.errors = self.errors.wrapping_add(1);
+                self.count = 0;
+                None
+            }
+            step => {
+                self.count += step;
+                let per = self.detent.transitions();
+                if self.count >= per {
+                    self.count = 0;
+                    Some(Direction::CW)
+                } else if self.count <= -per {
+                    self.count = 0;
+                    Some(Direction::CCW)
+                } else {
+                    None
+                }
+            }
         }
     }
 }
+
+impl<A: InputPin, B: InputPin> Encoder for Knob<A, B> {
+    fn poll_at(&mut self, now: u32) -> Option<i16> {
+        let dir = self.poll()?;
+        Some(dir.sign() * self.velocity.scale(now, dir))
+    }
+    fn set_acceleration(&mut self, accel: Option<Acceleration>) {
+        self.velocity.accel = accel;
+    }
+    // Invalid transitions seen so far; each one is a lost step
+    fn errors(&self) -> u32 {
+        self.errors
+    }
+}
+
+// A free running count of quadrature transitions, e.g. a timer in encoder
+// mode, see `qei`
+pub trait Counter {
+    fn count(&self) -> u16;
+}
+
+pub struct TimerKnob<C: Counter> {
+    counter: C,
+    last: u16,
+    detent: Detent,
+    count: i16,
+    velocity: Velocity,
+}
+
+impl<C: Counter> TimerKnob<C> {
+    pub fn new(counter: C, detent: Detent) -> Self {
+        let last = counter.count();
+        let count = 0;
+        let velocity = Velocity::new();
+        Self {
+            counter,
+            last,
+            detent,
+            count,
+            velocity,
+        }
+    }
+}
+
+impl<C: Counter> Encoder for TimerKnob<C> {
+    fn poll_at(&mut self, now: u32) -> Option<i16> {
+        let next = self.counter.count();
+        self.count += next.wrapping_sub(self.last) as i16;
+        self.last = next;
+        let per = self.detent.transitions() as i16;
+        let steps = self.count / per;
+        if steps == 0 {
+            return None;
+        }
+        self.count -= steps * per;
+        let dir = if steps < 0 {
+            Direction::CCW
+        } else {
+            Direction::CW
+        };
+        Some(steps * self.velocity.scale(now, dir))
+    }
+    fn set_acceleration(&mut self, accel: Option<Acceleration>) {
+        self.velocity.accel = accel;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    struct Pin<'a>(&'a Cell<bool>);
+
+    impl<'a> InputPin for Pin<'a> {
+        type Error = ();
+        fn is_high(&self) -> Result<bool, ()> {
+            Ok(self.0.get())
+        }
+        fn is_low(&self) -> Result<bool, ()> {
+            Ok(!self.0.get())
+        }
+    }
+
+    // Gray code, one turn clockwise from 00
+    const CW: [(bool, bool); 4] = [(false, true), (true, true), (true, false), (false, false)];
+
+    fn turn(
+        knob: &mut Knob<Pin, Pin>,
+        a: &Cell<bool>,
+        b: &Cell<bool>,
+        states: &[(bool, bool)],
+    ) -> (i16, i16) {
+        let (mut cw, mut ccw) = (0, 0);
+        for &(x, y) in states {
+            a.set(x);
+            b.set(y);
+            match knob.poll() {
+                Some(Direction::CW) => cw += 1,
+                Some(Direction::CCW) => ccw += 1,
+                None => {}
+            }
+        }
+        (cw, ccw)
+    }
+
+    #[test]
+    fn steps_per_detent() {
+        let (a, b) = (Cell::new(false), Cell::new(false));
+        let mut knob = Knob::with_detent(Pin(&a), Pin(&b), Detent::Four);
+        assert_eq!(turn(&mut knob, &a, &b, &CW), (1, 0));
+        let ccw = [(true, false), (true, true), (false, true), (false, false)];
+        assert_eq!(turn(&mut knob, &a, &b, &ccw), (0, 1));
+        assert_eq!(knob.errors(), 0);
+
+        let mut knob = Knob::new(Pin(&a), Pin(&b));
+        assert_eq!(turn(&mut knob, &a, &b, &CW), (4, 0));
+    }
+
+    #[test]
+    fn starts_from_the_resting_state() {
+        // Resting on 11, which a knob starting from 00 would take for a
+        // skipped transition
+        let (a, b) = (Cell::new(true), Cell::new(true));
+        let mut knob = Knob::with_detent(Pin(&a), Pin(&b), Detent::Two);
+        assert_eq!(knob.poll(), None);
+        assert_eq!(knob.errors(), 0);
+        assert_eq!(
+            turn(&mut knob, &a, &b, &[(true, false), (false, false)]),
+            (1, 0)
+        );
+    }
+
+    #[test]
+    fn skipped_transitions_drop_the_partial_detent() {
+        let (a, b) = (Cell::new(false), Cell::new(false));
+        let mut knob = Knob::with_detent(Pin(&a), Pin(&b), Detent::Four);
+        // Three quarters of a detent, then a jump across two states
+        let states = [(false, true), (true, true), (true, false), (false, true)];
+        assert_eq!(turn(&mut knob, &a, &b, &states), (0, 0));
+        assert_eq!(knob.errors(), 1);
+        // A whole detent from there still counts
+        let states = [(true, true), (true, false), (false, false), (false, true)];
+        assert_eq!(turn(&mut knob, &a, &b, &states), (1, 0));
+        // Bouncing back and forth on one edge goes nowhere
+        let states = [(false, false), (false, true), (false, false), (false, true)];
+        assert_eq!(turn(&mut knob, &a, &b, &states), (0, 0));
+        assert_eq!(knob.errors(), 1);
+    }
+
+    struct Timer<'a>(&'a Cell<u16>);
+
+    impl<'a> Counter for Timer<'a> {
+        fn count(&self) -> u16 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn timer_knob_counts_detents() {
+        // Starting near the top, so the count wraps
+        let cnt = Cell::new(0xfffe);
+        let mut knob = TimerKnob::new(Timer(&cnt), Detent::Four);
+        assert_eq!(knob.poll_at(0), None);
+        cnt.set(0x0001);
+        assert_eq!(knob.poll_at(1), None);
+        cnt.set(0x0002);
+        assert_eq!(knob.poll_at(2), Some(1));
+        // Several detents between polls come out at once, and the partial
+        // one carries over
+        cnt.set(0x000d);
+        assert_eq!(knob.poll_at(3), Some(2));
+        cnt.set(0x000e);
+        assert_eq!(knob.poll_at(4), Some(1));
+        cnt.set(0xfffe);
+        assert_eq!(knob.poll_at(5), Some(-4));
+        cnt.set(0xfffc);
+        assert_eq!(knob.poll_at(6), None);
+        assert_eq!(knob.errors(), 0);
+    }
+
+    #[test]
+    fn accelerates_within_the_window() {
+        let accel = Acceleration {
+            window: 1000,
+            max: 8,
+            curve: Curve::Linear,
+        };
+        assert_eq!(accel.scale(1000), 1);
+        assert_eq!(accel.scale(5000), 1);
+        assert_eq!(accel.scale(0), 8);
+        assert_eq!(accel.scale(500), 4);
+        let (a, b) = (Cell::new(false), Cell::new(false));
+        let mut knob = Knob::new(Pin(&a), Pin(&b));
+        knob.set_acceleration(Some(accel));
+        let mut now = 0;
+        let mut step = |x, y| {
+            a.set(x);
+            b.set(y);
+            now += 100;
+            knob.poll_at(now)
+        };
+        assert_eq!(step(false, true), Some(1));
+        assert_eq!(step(true, true), Some(7));
+        // Reversing starts over
+        assert_eq!(step(false, true), Some(-1));
+    }
+}
@@ -1,6 +1,37 @@
 #![no_std]
+pub mod app;
+pub mod button;
+#[cfg(feature = "device")]
+pub mod capture;
+pub mod color;
+pub mod console;
+pub mod diag;
+pub mod display;
+pub mod dmx;
+#[cfg(feature = "device")]
+pub mod flash;
+pub mod harmony;
 pub mod hsv;
+pub mod input;
+pub mod ir;
 pub mod knob;
 pub mod m6;
+pub mod menu;
+pub mod midi;
+pub mod palette;
+pub mod param;
+pub mod preset;
+pub mod preview;
+#[cfg(feature = "device")]
 pub mod pwmled;
+#[cfg(feature = "device")]
+pub mod qei;
+pub mod record;
+pub mod remote;
 pub mod render;
+pub mod serial;
+pub mod settings;
+pub mod stats;
+pub mod store;
+pub mod stream;
+pub mod tempo;
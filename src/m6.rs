@@ -1,9 +1,10 @@
 use heapless::{consts, String, Vec};
 use lazy_static::lazy_static;
 use num_rational::Ratio;
+use smart_leds::RGB8;
 
-use crate::hsv::HSV;
-use crate::knob::Direction;
+use crate::input::BANK_SIZE;
+use crate::param::Param;
 
 use core::iter::once;
 
@@ -60,26 +61,54 @@ fn build_nodes() -> Vec<Node, consts::U19> {
     center.chain(petals).chain(rays).chain(outer).collect()
 }
 
+// Two LEDs per node
+pub const LEDS: usize = 38;
+
 lazy_static! {
     static ref NODES: Vec<Node, consts::U19> = build_nodes();
 }
 
-// TODO -> AsRef<RGB>
+// In strip order, each node driving two LEDs
+pub fn nodes() -> &'static [Node] {
+    &NODES
+}
+
 pub trait Render {
-    fn render(&self, n: &Node) -> (HSV, HSV);
+    fn render(&self, n: &Node) -> (RGB8, RGB8);
     fn tick(&mut self) {}
     fn debug(&self) -> Vec<String<consts::U16>, consts::U8> {
         let rv = Vec::new();
         rv
     }
 
-    fn knob1(&mut self, _dir: Direction) {}
-    fn knob2(&mut self, _dir: Direction) {}
+    // Adjustable parameters, indexed by `param` below
+    fn params(&self) -> &'static [Param] {
+        &[]
+    }
+    fn get(&self, _param: usize) -> i16 {
+        0
+    }
+    fn set(&mut self, _param: usize, _value: i16) {}
+    // Parameter behind a knob slot; by default each bank holds the next
+    // BANK_SIZE parameters in order
+    fn knob_param(&self, bank: u8, slot: u8) -> Option<usize> {
+        let idx = (bank as usize * BANK_SIZE) + slot as usize;
+        if slot as usize >= BANK_SIZE || idx >= self.params().len() {
+            return None;
+        }
+        Some(idx)
+    }
+    fn adjust(&mut self, param: usize, delta: i16) {
+        if let Some(p) = self.params().get(param) {
+            let v = p.adjusted(self.get(param), delta);
+            self.set(param, v);
+        }
+    }
 }
 
 pub struct Generator<'a> {
     idx: usize,
-    carry: Option<HSV>,
+    carry: Option<RGB8>,
     r: &'a dyn Render,
 }
 
@@ -92,8 +121,8 @@ impl<'a> Generator<'a> {
 }
 
 impl<'a> Iterator for Generator<'a> {
-    type Item = HSV;
-    fn next(&mut self) -> Option<HSV> {
+    type Item = RGB8;
+    fn next(&mut self) -> Option<RGB8> {
         let carry = self.carry.take();
         if carry.is_some() {
             return carry;
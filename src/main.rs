@@ -10,19 +10,26 @@ extern crate panic_semihosting; // panic handler
 use cortex_m_semihosting::hprintln;
 
 //use embedded_hal::digital::v2::OutputPin;
+use core::fmt::Write;
+
+use embedded_hal::serial::Read;
+use heapless::{consts, String};
+
+use cortex_m::peripheral::DWT;
 use rtfm::{app, Instant};
 use stm32f1xx_hal::{
     afio::AfioExt,
     flash::FlashExt,
     gpio::{
-        gpioa::{PA5, PA6, PA7},
-        gpiob::{PB10, PB11, PB12, PB13, PB14, PB15},
+        gpioa::{PA10, PA2, PA3, PA5, PA6, PA7, PA9},
+        gpiob::{PB10, PB11, PB12, PB13, PB8, PB9},
         Alternate, Floating, GpioExt, Input, OpenDrain, PullDown, PushPull,
     },
     i2c::{BlockingI2c, DutyCycle, Mode},
     rcc::RccExt,
+    serial::{self as usart, Event, Rx, Serial, Tx},
     spi::Spi,
-    stm32::{I2C2, SPI1},
+    stm32::{I2C2, SPI1, TIM2, USART1, USART2},
     time::U32Ext,
 };
 
@@ -30,20 +37,165 @@ use apa102_spi::Apa102;
 #[allow(unused)]
 use smart_leds::{SmartLedsWrite, RGB8};
 
-use embedded_graphics::{fonts::Font6x8, prelude::*};
 use ssd1306::{interface::I2cInterface, prelude::*, Builder};
 
-use glow::knob::Knob;
-use glow::m6::{Generator, Render};
-use glow::render::{Breath, Rainbow, Zoom};
+use glow_proto::{FrameReader, Packet, Request as Message, Response, Status, MAX_FRAME};
+
+use glow::app::{App, Request};
+use glow::button::{Button, Gesture, Timing};
+use glow::capture::EdgeCapture;
+use glow::console::{self, Command, LineBuffer, ParseError};
+use glow::diag::Diagnostics;
+use glow::display::{self, Canvas, Level, Link, Screen, Shifted};
+use glow::dmx::{self, Control, Personality, Receiver, FOOTPRINT};
+use glow::flash::Flash;
+use glow::input::{Action, InputEvent, Mapping};
+use glow::ir::{Decoder, Keymap};
+use glow::knob::{Acceleration, Curve, Detent, Encoder, Knob};
+use glow::m6::{Render, LEDS};
+use glow::midi::{self, Message as MidiMessage, Parser};
+use glow::preview;
+use glow::record::{self, Recorder};
+use glow::remote;
+use glow::serial::{self as port, Writer};
+use glow::settings::{estimate_ma, Role};
+use glow::stats::FrameStats;
+use glow::store::{self, Store};
+use glow::stream::{Feed, StreamReader};
 
+#[cfg(feature = "knob2-timer")]
+use glow::knob::TimerKnob;
+#[cfg(feature = "knob2-timer")]
+use glow::qei::EncoderMode;
+#[cfg(not(feature = "knob2-timer"))]
+use stm32f1xx_hal::gpio::gpiob::{PB14, PB15};
+#[cfg(feature = "knob2-timer")]
+use stm32f1xx_hal::stm32::TIM4;
+
+// Core clock, which the DWT cycle counter runs at
+const SYSCLK: u32 = 24_000_000;
 const PERIOD: u32 = 800_000;
 const DEBUG_PERIOD: u32 = 8_000_000;
+// Debug ticks between burn-in shifts, a minute
+const SHIFT_PERIOD: u32 = 180;
+const KNOB_ACCEL: Acceleration = Acceleration {
+    // 50ms at 24MHz
+    window: 1_200_000,
+    max: 8,
+    curve: Curve::Quadratic,
+};
+// Longest pause within an Adalight or TPM2 frame, 10ms at 24MHz; at 115200
+// baud the bytes of a frame come about 87us apart
+const STREAM_GAP: u32 = 240_000;
+const BUTTON_TIMING: Timing = Timing {
+    // 5ms, 300ms and 600ms at 24MHz
+    debounce: 120_000,
+    double_click: 7_200_000,
+    long_press: 14_400_000,
+};
+
+const MAPPING: Mapping = Mapping::new();
+const MIDI_MAP: midi::Map = midi::Map::new();
+
+// Compiled in, like the other mappings: a different remote means editing
+// `Keymap::new` and reflashing
+const IR_KEYMAP: Keymap = Keymap::new();
+// Capture ticks of the infrared receiver's edges, so anything over half a
+// second wraps, which only ever happens between key presses. TIM2 runs at
+// SYSCLK.
+const IR_TICK_US: u32 = 8;
+const IR_PRESCALER: u16 = (SYSCLK / 1_000_000 * IR_TICK_US) as u16;
+
+// Records and applies the events; true if a dump was asked for
+fn dispatch(app: &mut App, rec: &mut Recorder, evs: &[Option<InputEvent>]) -> bool {
+    let mut dump = false;
+    for ev in evs.iter().filter_map(|ev| *ev) {
+        rec.record(ev, app);
+        match app.input(ev) {
+            Some(Request::Dump) => {
+                dump = true;
+            }
+            None => {}
+        }
+    }
+    dump
+}
+
+fn diagnostics(display: &Link, store_up: bool) -> Diagnostics {
+    Diagnostics {
+        display_up: display.is_up(),
+        i2c_errors: display.errors(),
+        store_up,
+    }
+}
+
+fn pressed(id: u8, g: Option<Gesture>) -> Option<InputEvent> {
+    g.map(|gesture| InputEvent::Button { id, gesture })
+}
+
+type Buttons = (Button<PB8<Input<PullDown>>>, Button<PB9<Input<PullDown>>>);
+
+// Reports the turn to a held button, which then skips its click
+fn turned(bs: &mut Buttons, id: u8, delta: i16) -> Option<Gesture> {
+    match id {
+        0 => bs.0.turned(delta),
+        1 => bs.1.turned(delta),
+        _ => None,
+    }
+}
+
+fn shifted(bs: &Buttons) -> bool {
+    match MAPPING.shift {
+        Some(0) => bs.0.is_pressed(),
+        Some(1) => bs.1.is_pressed(),
+        _ => false,
+    }
+}
+
+// Turning a knob while holding its own button is a gesture instead of a
+// plain turn, unless that button is the shift button
+fn rotated(id: u8, delta: Option<i16>, bs: &mut Buttons) -> Option<InputEvent> {
+    let delta = delta?;
+    let shift = match MAPPING.shift {
+        Some(s) => turned(bs, s, delta).is_some(),
+        None => false,
+    };
+    if MAPPING.shift != Some(id) {
+        match turned(bs, id, delta) {
+            Some(gesture) => {
+                return Some(InputEvent::Button { id, gesture });
+            }
+            None => {}
+        }
+    }
+    Some(InputEvent::Rotate { id, delta, shift })
+}
+
+// Changes USART2's rate on the fly, since the HAL only sets it once. APB1
+// runs at SYSCLK, as the clocks leave its prescaler at 1 below 36MHz.
+fn set_baud(baud: u32) {
+    let usart = unsafe { &*USART2::ptr() };
+    usart.cr1.modify(|_r, w| w.ue().clear_bit());
+    usart.brr.write(|w| unsafe { w.bits(SYSCLK / baud) });
+    usart.cr1.modify(|_r, w| w.ue().set_bit());
+}
+
+// Either backend works for either knob. The second knob is on PB14/PB15,
+// unless the knob2-timer feature moves it to TIM4's CH1/CH2, PB6/PB7, so
+// the timer counts its steps while `tick` is busy
+type Knob1 = Knob<PB12<Input<PullDown>>, PB13<Input<PullDown>>>;
+#[cfg(not(feature = "knob2-timer"))]
+type Knob2 = Knob<PB14<Input<PullDown>>, PB15<Input<PullDown>>>;
+#[cfg(feature = "knob2-timer")]
+type Knob2 = TimerKnob<TIM4>;
+// Whether the second knob's pins interrupt on EXTI14 and EXTI15
+const KNOB2_EXTI: bool = cfg!(not(feature = "knob2-timer"));
 
 #[app(device = stm32f1::stm32f103)]
 const APP: () = {
-    static mut knob: Knob<PB12<Input<PullDown>>, PB13<Input<PullDown>>> = ();
-    static mut knob2: Knob<PB14<Input<PullDown>>, PB15<Input<PullDown>>> = ();
+    static mut knob: Knob1 = ();
+    static mut knob2: Knob2 = ();
+    static mut buttons: Buttons = ();
     static mut screen: GraphicsMode<
         I2cInterface<BlockingI2c<I2C2, (PB10<Alternate<OpenDrain>>, PB11<Alternate<OpenDrain>>)>>,
     > = ();
@@ -58,11 +210,32 @@ const APP: () = {
             ),
         >,
     > = ();
-    static mut rainbow: Rainbow = Rainbow::new();
-    static mut breath: Breath = Breath::new();
-    static mut zoom: Zoom = Zoom::new();
+    static mut serial_tx: Tx<USART1> = ();
+    static mut serial_rx: Rx<USART1> = ();
+    static mut lines: LineBuffer = ();
+    static mut streams: StreamReader = StreamReader::with_gap(STREAM_GAP);
+    static mut proto_tx: Tx<USART2> = ();
+    static mut proto_rx: Rx<USART2> = ();
+    static mut frames: FrameReader = FrameReader::new();
+    static mut dmx_rx: Receiver = Receiver::new();
+    static mut midi_rx: Parser = Parser::new();
+    // Set from the port setting by debug_tick
+    static mut role: Role = Role::Protocol;
+    static mut ir_timer: TIM2 = ();
+    // None if the flash wouldn't take it, leaving the defaults unsaved
+    static mut store: Option<Store<Flash>> = ();
+    // Whether `store` opened, for diagnostics
+    static store_up: bool = ();
+    static mut app: App = App::new(MAPPING);
+    static mut recorder: Recorder = Recorder::new();
+    static mut display: Link = Link::new();
+    static mut stats: FrameStats = FrameStats::new(SYSCLK);
 
-    #[init(schedule = [tick, debug_tick])]
+    #[init(
+        schedule = [tick, debug_tick],
+        spawn = [report],
+        resources = [display, app, recorder]
+    )]
     fn init() -> init::LateResources {
         let rcc = device.RCC;
         let afio = device.AFIO;
@@ -70,6 +243,10 @@ const APP: () = {
 
         rcc.apb2enr
             .modify(|_r, w| w.afioen().enabled().spi1en().enabled());
+        rcc.apb1enr
+            .modify(|_r, w| w.tim2en().enabled().tim4en().bit(!KNOB2_EXTI));
+        afio.exticr3
+            .modify(|_r, w| unsafe { w.exti8().bits(0b001).exti9().bits(0b001) });
         afio.exticr4.modify(|_r, w| unsafe {
             w.exti12()
                 .bits(0b001)
@@ -77,18 +254,56 @@ const APP: () = {
                 .bits(0b001)
                 .exti14()
                 .bits(0b001)
+                .exti15()
+                .bits(0b001)
         });
 
-        // Enable EXT Interrupts 12-14
-        exti.imr
-            .modify(|_r, w| w.mr12().set_bit().mr13().set_bit().mr14().set_bit());
+        // Enable EXT Interrupts 8-9 (buttons) and 12-15 (knobs)
+        exti.imr.modify(|_r, w| {
+            w.mr8()
+                .set_bit()
+                .mr9()
+                .set_bit()
+                .mr12()
+                .set_bit()
+                .mr13()
+                .set_bit()
+                .mr14()
+                .bit(KNOB2_EXTI)
+                .mr15()
+                .bit(KNOB2_EXTI)
+        });
 
-        // Enable rising trigger for 12-14
-        exti.rtsr
-            .modify(|_r, w| w.tr12().set_bit().tr13().set_bit().tr14().set_bit());
-        // Enable falling trigger for 12-14
-        exti.ftsr
-            .modify(|_r, w| w.tr12().set_bit().tr13().set_bit().tr14().set_bit());
+        // Enable rising trigger for 8-9 and 12-15
+        exti.rtsr.modify(|_r, w| {
+            w.tr8()
+                .set_bit()
+                .tr9()
+                .set_bit()
+                .tr12()
+                .set_bit()
+                .tr13()
+                .set_bit()
+                .tr14()
+                .set_bit()
+                .tr15()
+                .set_bit()
+        });
+        // Enable falling trigger for 8-9 and 12-15
+        exti.ftsr.modify(|_r, w| {
+            w.tr8()
+                .set_bit()
+                .tr9()
+                .set_bit()
+                .tr12()
+                .set_bit()
+                .tr13()
+                .set_bit()
+                .tr14()
+                .set_bit()
+                .tr15()
+                .set_bit()
+        });
 
         let mut rcc = rcc.constrain();
         let mut flash = device.FLASH.constrain();
@@ -104,10 +319,28 @@ const APP: () = {
 
         let k1a = gpiob.pb12.into_pull_down_input(&mut gpiob.crh);
         let k1b = gpiob.pb13.into_pull_down_input(&mut gpiob.crh);
-        let k2a = gpiob.pb14.into_pull_down_input(&mut gpiob.crh);
-        let k2b = gpiob.pb15.into_pull_down_input(&mut gpiob.crh);
-        let knob = Knob::new(k1a, k1b);
-        let knob2 = Knob::new(k2a, k2b);
+        let mut knob = Knob::with_detent(k1a, k1b, Detent::Four);
+        #[cfg(not(feature = "knob2-timer"))]
+        let mut knob2 = {
+            let k2a = gpiob.pb14.into_pull_down_input(&mut gpiob.crh);
+            let k2b = gpiob.pb15.into_pull_down_input(&mut gpiob.crh);
+            Knob::with_detent(k2a, k2b, Detent::Four)
+        };
+        #[cfg(feature = "knob2-timer")]
+        let mut knob2 = {
+            let _k2a = gpiob.pb6.into_pull_down_input(&mut gpiob.crl);
+            let _k2b = gpiob.pb7.into_pull_down_input(&mut gpiob.crl);
+            TimerKnob::new(device.TIM4.encoder_mode(), Detent::Four)
+        };
+        knob.set_acceleration(Some(KNOB_ACCEL));
+        knob2.set_acceleration(Some(KNOB_ACCEL));
+
+        let b1 = gpiob.pb8.into_pull_down_input(&mut gpiob.crh);
+        let b2 = gpiob.pb9.into_pull_down_input(&mut gpiob.crh);
+        let buttons = (
+            Button::new(b1, false, BUTTON_TIMING),
+            Button::new(b2, false, BUTTON_TIMING),
+        );
 
         let pa5 = gpioa.pa5.into_alternate_push_pull(&mut gpioa.crl);
         let pa6 = gpioa.pa6.into_floating_input(&mut gpioa.crl);
@@ -125,6 +358,50 @@ const APP: () = {
         );
         let led_strip = Apa102::new(spi);
 
+        let pa9: PA9<Alternate<PushPull>> = gpioa.pa9.into_alternate_push_pull(&mut gpioa.crh);
+        let pa10: PA10<Input<Floating>> = gpioa.pa10;
+        let mut serial = Serial::usart1(
+            device.USART1,
+            (pa9, pa10),
+            &mut afio.mapr,
+            115_200.bps(),
+            clocks,
+            &mut rcc.apb2,
+        );
+        serial.listen(Event::Rxne);
+        let (serial_tx, serial_rx) = serial.split();
+        let lines = LineBuffer::new();
+
+        let pa2: PA2<Alternate<PushPull>> = gpioa.pa2.into_alternate_push_pull(&mut gpioa.crl);
+        let pa3: PA3<Input<Floating>> = gpioa.pa3;
+        let mut proto = Serial::usart2(
+            device.USART2,
+            (pa2, pa3),
+            &mut afio.mapr,
+            Role::Protocol.baud().bps(),
+            clocks,
+            &mut rcc.apb1,
+        );
+        proto.listen(Event::Rxne);
+        let (proto_tx, proto_rx) = proto.split();
+
+        // Infrared receiver, on TIM2 CH1
+        let _pa0 = gpioa.pa0.into_floating_input(&mut gpioa.crl);
+        let ir_timer = device.TIM2.edge_capture(IR_PRESCALER);
+
+        // Settings and presets as they were at the last save. Recordings
+        // start from what was loaded, so dumps replay from it rather than
+        // from the defaults.
+        let mut store = Store::open(Flash::new()).ok();
+        match store.as_mut() {
+            Some(s) => {
+                let _ = store::load(s, resources.app);
+                resources.recorder.checkpoint(resources.app);
+            }
+            None => {}
+        }
+        let store_up = store.is_some();
+
         let pb10 = gpiob.pb10.into_alternate_open_drain(&mut gpiob.crh);
         let pb11 = gpiob.pb11.into_alternate_open_drain(&mut gpiob.crh);
         let i2c_pins = (pb10, pb11);
@@ -147,8 +424,11 @@ const APP: () = {
             //.with_rotation(DisplayRotation::Rotate90)
             .connect_i2c(i2c)
             .into();
-        screen.init().unwrap();
-        screen.flush().unwrap();
+        // Without a display the LEDs still run; debug_tick keeps retrying
+        resources
+            .display
+            .done(screen.init().and_then(|_| screen.flush()));
+        let _ = spawn.report(diagnostics(resources.display, store_up));
 
         schedule.tick(Instant::now() + PERIOD.cycles()).unwrap();
         schedule
@@ -157,62 +437,514 @@ const APP: () = {
         init::LateResources {
             knob,
             knob2,
+            buttons,
             led_strip,
             screen,
+            serial_tx,
+            serial_rx,
+            lines,
+            proto_tx,
+            proto_rx,
+            ir_timer,
+            store,
+            store_up,
+        }
+    }
+
+    #[interrupt(resources = [buttons, app, recorder], priority = 1, spawn = [dump])]
+    fn EXTI9_5() {
+        let now = DWT::get_cycle_count();
+        let evs = resources
+            .buttons
+            .lock(|bs| [pressed(0, bs.0.poll(now)), pressed(1, bs.1.poll(now))]);
+        let recorder = &mut resources.recorder;
+        if resources
+            .app
+            .lock(|app| recorder.lock(|rec| dispatch(app, rec, &evs)))
+        {
+            let _ = spawn.dump();
         }
     }
 
-    #[interrupt(resources = [knob, knob2, rainbow, breath, zoom], priority=1)]
+    // The GPIO knobs; with knob2-timer, TIM4 counts the second one's steps
+    // for `tick`, and polling it here only picks them up early
+    #[interrupt(resources = [knob, knob2, buttons, app, recorder], priority=1, spawn = [dump])]
     fn EXTI15_10() {
-        let k1 = &mut resources.knob;
-        let k2 = &mut resources.knob2;
-        resources.rainbow.lock(|r| {
-            match k1.poll() {
-                Some(x) => {
-                    r.knob1(x);
-                }
-                None => {}
-            }
-            match k2.poll() {
-                Some(x) => {
-                    r.knob2(x);
-                }
-                None => {}
-            }
-        });
+        let now = DWT::get_cycle_count();
+        let d1 = resources.knob.lock(|k| k.poll_at(now));
+        let d2 = resources.knob2.lock(|k| k.poll_at(now));
+        let evs = resources
+            .buttons
+            .lock(|bs| [rotated(0, d1, bs), rotated(1, d2, bs)]);
+        let recorder = &mut resources.recorder;
+        if resources
+            .app
+            .lock(|app| recorder.lock(|rec| dispatch(app, rec, &evs)))
+        {
+            let _ = spawn.dump();
+        }
     }
 
-    #[task(resources = [led_strip, rainbow, breath, zoom], schedule = [tick], priority = 3)]
+    #[task(
+        resources = [led_strip, knob, knob2, buttons, app, recorder, stats],
+        schedule = [tick],
+        spawn = [dump],
+        priority = 3
+    )]
     fn tick() {
-        //let r = resources.breath as &mut dyn Render;
         let ls = resources.led_strip;
-        resources.rainbow.lock(|r| {
-            let g: Generator = Generator::new(r);
-            let _ = ls.write(g);
-            r.tick();
+        // Long press and click timeouts happen while the pins are idle, and
+        // timer backed knobs have no interrupt of their own
+        let now = DWT::get_cycle_count();
+        let bs = resources.buttons;
+        let evs = [
+            pressed(0, bs.0.poll(now)),
+            pressed(1, bs.1.poll(now)),
+            rotated(0, resources.knob.poll_at(now), bs),
+            rotated(1, resources.knob2.poll_at(now), bs),
+        ];
+        let rec = resources.recorder;
+        let mut leds = [RGB8::default(); LEDS];
+        let start = DWT::get_cycle_count();
+        let dump = resources.app.lock(|app| {
+            let dump = dispatch(app, rec, &evs);
+            app.frame(&mut leds);
+            dump
         });
+        let rendered = DWT::get_cycle_count();
+        let _ = ls.write(leds.iter().cloned());
+        let written = DWT::get_cycle_count();
+        let late = Instant::now().duration_since(scheduled).as_cycles();
+        resources.stats.frame(
+            written,
+            rendered.wrapping_sub(start),
+            written.wrapping_sub(rendered),
+            late,
+            PERIOD,
+        );
+        rec.next_frame();
+        if dump {
+            let _ = spawn.dump();
+        }
         schedule.tick(scheduled + PERIOD.cycles()).unwrap();
     }
 
-    #[task(resources = [screen, rainbow, breath, zoom], schedule = [debug_tick], priority=2)]
+    #[task(
+        resources = [screen, display, store_up, buttons, app, stats, role],
+        schedule = [debug_tick],
+        spawn = [report, save],
+        priority = 2
+    )]
     fn debug_tick() {
-        let dbgv = resources.rainbow.lock(|r| r.debug());
-        let _ = resources.screen.clear();
-        for i in 0..(dbgv.len()) {
-            resources.screen.draw(
-                Font6x8::render_str(dbgv[i].as_str())
-                    .with_stroke(Some(1u8.into()))
-                    .translate(Coord::new(0, 8 * (i as i32)))
-                    .into_iter(),
-            );
-        }
-        let _ = resources.screen.flush();
+        static mut STEP: u32 = 0;
+        *STEP = STEP.wrapping_add(1);
         schedule
             .debug_tick(scheduled + DEBUG_PERIOD.cycles())
             .unwrap();
+        let (unsaved, port) = resources
+            .app
+            .lock(|app| (app.take_unsaved(), app.settings.port));
+        if unsaved {
+            let _ = spawn.save();
+        }
+        resources.role.lock(|role| {
+            if *role != port {
+                set_baud(port.baud());
+                *role = port;
+            }
+        });
+        let link = resources.display;
+        let screen = resources.screen;
+        if !link.is_up() {
+            if !link.retry() {
+                return;
+            }
+            if link.done(screen.init()) {
+                let _ = spawn.report(diagnostics(link, *resources.store_up));
+            }
+            if !link.is_up() {
+                return;
+            }
+        }
+        let bank = resources.buttons.lock(|bs| shifted(bs)) as u8;
+        let stats = resources.stats.lock(|s| *s);
+        let offset = display::shift(*STEP / SHIFT_PERIOD);
+        screen.clear();
+        let (level, leds) = resources.app.lock(|app| {
+            let level = app.screen_level();
+            let c = &mut Shifted::new(&mut *screen, offset);
+            if level == Level::Off {
+                return (level, None);
+            }
+            if app.menu.is_open() {
+                app.menu.draw(c, &app.patterns, &app.settings);
+                return (level, None);
+            }
+            let leds = match app.settings.screen {
+                Screen::Debug => {
+                    let dbgv = app.patterns.debug();
+                    for i in 0..(dbgv.len().min(2)) {
+                        c.text(i as u8, dbgv[i].as_str());
+                    }
+                    // Which parameters the knobs currently address, above
+                    // the status row
+                    c.text(2, app.patterns.bank_label(bank).as_str());
+                    None
+                }
+                Screen::Preview => {
+                    c.text(0, app.patterns.name());
+                    Some(*app.last_frame())
+                }
+            };
+            stats.draw(c, estimate_ma(app.last_frame()));
+            (level, leds)
+        });
+        // Drawn outside the lock, the tick task needs the app meanwhile
+        match leds {
+            Some(leds) => preview::draw(&mut Shifted::new(&mut *screen, offset), &leds),
+            None => {}
+        }
+        if level == Level::Dim {
+            display::dim(screen);
+        }
+        if link.done(screen.flush()) {
+            let _ = spawn.report(diagnostics(link, *resources.store_up));
+        }
+    }
+    // The console, with Adalight and TPM2 frames picked out of its bytes,
+    // see `stream`; high for the same reason as USART2
+    #[interrupt(
+        resources = [serial_rx, lines, streams],
+        priority = 4,
+        spawn = [command, show]
+    )]
+    fn USART1() {
+        let b = match resources.serial_rx.read() {
+            Ok(b) => b,
+            Err(_) => return,
+        };
+        match resources.streams.push_at(b, DWT::get_cycle_count()) {
+            Feed::Text(text) => {
+                for &b in text.as_slice() {
+                    match resources.lines.push(b) {
+                        Some(line) => {
+                            let _ = spawn.command(line);
+                        }
+                        None => {}
+                    }
+                }
+            }
+            Feed::Frame(_) => {
+                let mut rgb = [0; LEDS * 3];
+                rgb.copy_from_slice(resources.streams.frame());
+                // Dropped if the last one is still waiting, as it will be
+                // replaced soon anyway
+                let _ = spawn.show(rgb);
+            }
+            Feed::Pending | Feed::Error(_) => {}
+        }
+    }
+
+    #[task(resources = [app], priority = 1)]
+    fn show(rgb: [u8; LEDS * 3]) {
+        resources.app.lock(|app| app.push_frame(&rgb));
+    }
+
+    // Replies are written from here, which is fine for someone typing at it
+    #[task(
+        resources = [serial_tx, app, recorder, display, store_up, stats],
+        priority = 1,
+        spawn = [dump]
+    )]
+    fn command(line: String<consts::U64>) {
+        let mut w = Writer(resources.serial_tx);
+        let cmd = match console::parse(&line) {
+            Ok(cmd) => cmd,
+            Err(ParseError::Empty) => return,
+            Err(e) => {
+                let _ = write!(w, "error: {}\r\n", e);
+                return;
+            }
+        };
+        if cmd == Command::Diag {
+            let store_up = *resources.store_up;
+            let diag = resources.display.lock(|d| diagnostics(d, store_up));
+            let stats = resources.stats.lock(|s| *s);
+            let ma = resources.app.lock(|app| estimate_ma(app.last_frame()));
+            let _ = write!(w, "{}\r\n{}, {} mA\r\n", diag, stats, ma);
+            return;
+        }
+        // Written out once the app is unlocked
+        let mut reply = console::Reply::new();
+        let action = resources
+            .app
+            .lock(|app| console::execute(cmd, app, &mut reply));
+        let _ = w.write_str(reply.as_str());
+        if reply.is_truncated() {
+            let _ = w.write_str("\r\nerror: reply truncated\r\n");
+        }
+        let ev = match action {
+            Ok(Some(action)) => InputEvent::Command(action),
+            _ => return,
+        };
+        let recorder = &mut resources.recorder;
+        if resources
+            .app
+            .lock(|app| recorder.lock(|rec| dispatch(app, rec, &[Some(ev)])))
+        {
+            let _ = spawn.dump();
+        }
+    }
+
+    // Above everything else, since there is no FIFO and a frame of LEDs
+    // arrives back to back; whole packets are handled in `remote`, or in
+    // `dmx` or `midi`
+    #[interrupt(
+        resources = [proto_rx, frames, dmx_rx, midi_rx, role],
+        priority = 4,
+        spawn = [remote, dmx, midi]
+    )]
+    fn USART2() {
+        static mut LAST: Role = Role::Protocol;
+        let role = *resources.role;
+        if role != *LAST {
+            // Whatever they held came at the old rate
+            *LAST = role;
+            resources.dmx_rx.error();
+            *resources.midi_rx = Parser::new();
+            let _ = resources.frames.push(0);
+        }
+        let read = resources.proto_rx.read();
+        if role == Role::Midi {
+            match read.ok().and_then(|b| resources.midi_rx.push(b)) {
+                Some(msg) => {
+                    let _ = spawn.midi(msg);
+                }
+                None => {}
+            }
+            return;
+        }
+        if role == Role::Dmx {
+            let rx = resources.dmx_rx;
+            let done = match read {
+                Ok(b) => rx.push(b),
+                // A break
+                Err(nb::Error::Other(usart::Error::Framing)) => rx.line_break(),
+                Err(nb::Error::Other(_)) => {
+                    rx.error();
+                    false
+                }
+                Err(nb::Error::WouldBlock) => false,
+            };
+            if done {
+                // Dropped while the last one is still being handled, as
+                // the desk sends it again shortly
+                let _ = spawn.dmx(*rx.window());
+            }
+            return;
+        }
+        let b = match read {
+            Ok(b) => b,
+            Err(_) => return,
+        };
+        match resources.frames.push(b) {
+            // Dropped while the last one is still being handled; the host
+            // waits for each reply, so it only happens after a timeout
+            Some(Ok(packet)) => {
+                let _ = spawn.remote(packet);
+            }
+            // Corrupt frames are left for the host to time out and retry
+            Some(Err(_)) | None => {}
+        }
+    }
+
+    #[task(
+        resources = [proto_tx, app, recorder, display, store_up, stats],
+        priority = 1,
+        spawn = [dump]
+    )]
+    fn remote(packet: Packet) {
+        let response = match packet.request() {
+            Ok(req) => {
+                let reply = resources.app.lock(|app| remote::handle(&req, app));
+                match reply {
+                    remote::Reply::Answer(r) => r,
+                    remote::Reply::Dispatch(action) => {
+                        let ev = Some(InputEvent::Command(action));
+                        let recorder = &mut resources.recorder;
+                        if resources
+                            .app
+                            .lock(|app| recorder.lock(|rec| dispatch(app, rec, &[ev])))
+                        {
+                            let _ = spawn.dump();
+                        }
+                        Response::Ack
+                    }
+                    remote::Reply::Push => match req {
+                        Message::PushFrame(rgb) => {
+                            if resources.app.lock(|app| app.push_frame(rgb)) {
+                                Response::Ack
+                            } else {
+                                Response::Nack(Status::BadRequest)
+                            }
+                        }
+                        _ => Response::Nack(Status::BadRequest),
+                    },
+                    remote::Reply::Diag => {
+                        let store_up = *resources.store_up;
+                        let diag = resources.display.lock(|d| diagnostics(d, store_up));
+                        let stats = resources.stats.lock(|s| *s);
+                        let ma = resources.app.lock(|app| estimate_ma(app.last_frame()));
+                        Response::Diag {
+                            display_up: diag.display_up,
+                            i2c_errors: diag.i2c_errors,
+                            fps: stats.fps,
+                            overruns: stats.overruns,
+                            render_us: stats.micros(stats.render),
+                            write_us: stats.micros(stats.write),
+                            ma,
+                        }
+                    }
+                }
+            }
+            Err(_) => Response::Nack(Status::BadRequest),
+        };
+        let mut buf = [0; MAX_FRAME];
+        if let Ok(n) = response.encode(packet.id(), &mut buf) {
+            let _ = port::write_all(resources.proto_tx, &buf[..n]);
+        }
+    }
+
+    #[task(resources = [app, recorder, dmx_rx], priority = 1, spawn = [dump])]
+    fn dmx(window: [u8; FOOTPRINT]) {
+        static mut CONTROL: Control = Control::new();
+        let (address, mode) = resources
+            .app
+            .lock(|app| (app.settings.dmx_address, app.settings.dmx_mode));
+        resources.dmx_rx.lock(|rx| rx.set_address(address));
+        let actions = resources.app.lock(|app| match mode {
+            Personality::Control => CONTROL.apply(&window, app),
+            Personality::Direct => {
+                app.push_frame(&window);
+                // So the levels all apply when switching back
+                *CONTROL = Control::new();
+                [None; dmx::CONTROL]
+            }
+        });
+        let mut evs = [None; dmx::CONTROL];
+        for (ev, action) in evs.iter_mut().zip(actions.iter()) {
+            *ev = action.map(InputEvent::Command);
+        }
+        let recorder = &mut resources.recorder;
+        if resources
+            .app
+            .lock(|app| recorder.lock(|rec| dispatch(app, rec, &evs)))
+        {
+            let _ = spawn.dump();
+        }
+    }
+
+    // Edges are captured by the timer, so only reading them back has to
+    // keep up; keys are handled in `infrared`
+    #[interrupt(resources = [ir_timer], priority = 4, spawn = [infrared])]
+    fn TIM2() {
+        static mut LAST: u16 = 0;
+        static mut DECODER: Decoder = Decoder::new();
+        while let Some((rising, count)) = resources.ir_timer.edge() {
+            let us = count.wrapping_sub(*LAST) as u32 * IR_TICK_US;
+            *LAST = count;
+            // The receiver pulls low while it sees the carrier, so a rising
+            // edge ends a mark
+            let key = DECODER.push(rising, us);
+            match key.and_then(|key| IR_KEYMAP.action(key)) {
+                Some(action) => {
+                    let _ = spawn.infrared(action);
+                }
+                None => {}
+            }
+        }
+    }
+
+    #[task(resources = [app, recorder], priority = 1, spawn = [dump])]
+    fn infrared(action: Action) {
+        let ev = Some(InputEvent::Command(action));
+        let recorder = &mut resources.recorder;
+        if resources
+            .app
+            .lock(|app| recorder.lock(|rec| dispatch(app, rec, &[ev])))
+        {
+            let _ = spawn.dump();
+        }
     }
+
+    // Clock is a couple of pulses a frame, so there is room for a few
+    #[task(resources = [app, recorder], priority = 1, capacity = 8, spawn = [dump])]
+    fn midi(msg: MidiMessage) {
+        let action = resources.app.lock(|app| {
+            match msg {
+                MidiMessage::Clock => app.tempo.pulse(),
+                MidiMessage::Start => app.tempo.start(),
+                MidiMessage::Continue => app.tempo.resume(),
+                MidiMessage::Stop => app.tempo.stop(),
+                _ => return MIDI_MAP.action(msg, app),
+            }
+            None
+        });
+        let ev = action.map(InputEvent::Command);
+        let recorder = &mut resources.recorder;
+        if resources
+            .app
+            .lock(|app| recorder.lock(|rec| dispatch(app, rec, &[ev])))
+        {
+            let _ = spawn.dump();
+        }
+    }
+
+    // A record at a time, so the ring keeps filling while it prints
+    #[task(resources = [recorder, serial_tx], priority = 1)]
+    fn dump() {
+        let mut w = Writer(resources.serial_tx);
+        let (first, end, snapshot) = resources.recorder.lock(|rec| {
+            let (first, end) = rec.span();
+            (first, end, rec.snapshot(first))
+        });
+        let _ = record::write_header(&mut w, first, snapshot.as_ref());
+        for n in first..end {
+            match resources.recorder.lock(|rec| rec.get(n)) {
+                Some(r) => {
+                    let _ = write!(w, "{}\r\n", r);
+                }
+                None => {
+                    let _ = write!(w, "{} while dumping\r\n", record::TRUNCATED);
+                    return;
+                }
+            }
+        }
+    }
+    // The app stays locked throughout, but the flash stalls the core while
+    // it programs anyway
+    #[task(resources = [app, store, serial_tx], priority = 1)]
+    fn save() {
+        let store = match resources.store {
+            Some(store) => store,
+            None => return,
+        };
+        match resources.app.lock(|app| store::save(store, app)) {
+            Err(e) => {
+                let _ = write!(Writer(resources.serial_tx), "# store: {:?}\r\n", e);
+            }
+            Ok(()) => {}
+        }
+    }
+    // As a `#` line, which glow-replay skips if it lands in a dump
+    #[task(resources = [serial_tx], priority = 1, capacity = 4)]
+    fn report(diag: Diagnostics) {
+        let _ = write!(Writer(resources.serial_tx), "# {}\r\n", diag);
+    }
+
     extern "C" {
         fn EXTI0();
-        fn USART1();
+        fn EXTI1();
+        fn EXTI2();
     }
 };
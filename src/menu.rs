@@ -0,0 +1,287 @@
+use core::fmt::Write;
+
+use heapless::{consts, String};
+
+use crate::button::Gesture;
+use crate::display::{Canvas, ROWS};
+use crate::input::InputEvent;
+use crate::m6::Render;
+use crate::render::{Patterns, NAMES};
+use crate::settings::Settings;
+
+const MAIN: [&str; 3] = ["patterns", "parameters", "settings"];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Page {
+    Closed,
+    Main,
+    Patterns,
+    Params,
+    Settings,
+    // Editing one entry of the list page, by index
+    EditParam(u8),
+    EditSetting(u8),
+}
+
+// Knobs move the cursor or change the value being edited, a click selects
+// and a long or double click goes back a page
+pub struct Menu {
+    page: Page,
+    cursor: u8,
+}
+
+impl Menu {
+    pub const fn new() -> Self {
+        let page = Page::Closed;
+        let cursor = 0;
+        Self { page, cursor }
+    }
+    pub fn page(&self) -> Page {
+        self.page
+    }
+    pub fn is_open(&self) -> bool {
+        self.page != Page::Closed
+    }
+    pub fn open(&mut self) {
+        self.page = Page::Main;
+        self.cursor = 0;
+    }
+    // Returns false for events the menu leaves alone, which is all of them
+    // while it is closed; serial commands always go straight through
+    pub fn input(&mut self, ev: InputEvent, p: &mut Patterns, s: &mut Settings) -> bool {
+        if !self.is_open() {
+            return false;
+        }
+        match ev {
+            InputEvent::Rotate { delta, .. } => self.turn(delta, p, s),
+            InputEvent::Button { gesture, .. } => match gesture {
+                Gesture::Click => self.select(p),
+                Gesture::DoubleClick | Gesture::LongPress => self.back(),
+                Gesture::HoldTurn(delta) => self.turn(delta, p, s),
+            },
+            InputEvent::Command(_) => return false,
+        }
+        true
+    }
+    fn len(&self, p: &Patterns, s: &Settings) -> usize {
+        match self.page {
+            Page::Closed => 0,
+            Page::Main => MAIN.len(),
+            Page::Patterns => NAMES.len(),
+            Page::Params => p.params().len(),
+            Page::Settings => s.params().len(),
+            Page::EditParam(_) | Page::EditSetting(_) => 1,
+        }
+    }
+    fn turn(&mut self, delta: i16, p: &mut Patterns, s: &mut Settings) {
+        match self.page {
+            Page::EditParam(i) => p.adjust(i as usize, delta),
+            Page::EditSetting(i) => s.adjust(i as usize, delta),
+            _ => {
+                let last = self.len(p, s).saturating_sub(1) as i16;
+                let cursor = self.cursor as i16 + delta;
+                self.cursor = cursor.max(0).min(last) as u8;
+            }
+        }
+    }
+    fn select(&mut self, p: &mut Patterns) {
+        let (page, cursor) = match self.page {
+            Page::Main => match self.cursor {
+                0 => (Page::Patterns, p.active() as u8),
+                1 => (Page::Params, 0),
+                _ => (Page::Settings, 0),
+            },
+            Page::Patterns => {
+                p.select(self.cursor as usize);
+                (Page::Patterns, self.cursor)
+            }
+            // Pattern without parameters
+            Page::Params if p.params().is_empty() => (Page::Params, 0),
+            Page::Params => (Page::EditParam(self.cursor), 0),
+            Page::Settings => (Page::EditSetting(self.cursor), 0),
+            Page::EditParam(i) => (Page::Params, i),
+            Page::EditSetting(i) => (Page::Settings, i),
+            Page::Closed => (Page::Closed, 0),
+        };
+        self.page = page;
+        self.cursor = cursor;
+    }
+    fn back(&mut self) {
+        let (page, cursor) = match self.page {
+            Page::Closed | Page::Main => (Page::Closed, 0),
+            Page::Patterns => (Page::Main, 0),
+            Page::Params => (Page::Main, 1),
+            Page::Settings => (Page::Main, 2),
+            Page::EditParam(i) => (Page::Params, i),
+            Page::EditSetting(i) => (Page::Settings, i),
+        };
+        self.page = page;
+        self.cursor = cursor;
+    }
+    // A title row, then a window of the list around the cursor, or the
+    // value being edited
+    pub fn draw(&self, c: &mut impl Canvas, p: &Patterns, s: &Settings) {
+        let title = match self.page {
+            Page::Closed => return,
+            Page::Main => "menu",
+            Page::Patterns => "patterns",
+            Page::Params | Page::EditParam(_) => p.name(),
+            Page::Settings | Page::EditSetting(_) => "settings",
+        };
+        c.text(0, title);
+        let mut line: String<consts::U32> = String::new();
+        match self.page {
+            Page::EditParam(i) => {
+                let param = p.params().get(i as usize).map_or("", |x| x.name);
+                let _ = write!(line, "{}: < {} >", param, p.get(i as usize));
+                c.text(1, &line);
+                return;
+            }
+            Page::EditSetting(i) => {
+                let param = s.params().get(i as usize).map_or("", |x| x.name);
+                let _ = write!(line, "{}: < {} >", param, s.get(i as usize));
+                c.text(1, &line);
+                return;
+            }
+            _ => {}
+        }
+        let visible = ROWS - 1;
+        let cursor = self.cursor as usize;
+        let first = (cursor + 1).saturating_sub(visible);
+        for (row, i) in (first..self.len(p, s)).take(visible).enumerate() {
+            line = String::new();
+            let mark = if i == cursor { '>' } else { ' ' };
+            let _ = match self.page {
+                Page::Main => write!(line, "{}{}", mark, MAIN[i]),
+                Page::Patterns => {
+                    let on = if i == p.active() { "*" } else { "" };
+                    write!(line, "{}{}{}", mark, NAMES[i], on)
+                }
+                Page::Params => write!(line, "{}{} {}", mark, p.params()[i].name, p.get(i)),
+                Page::Settings => write!(line, "{}{} {}", mark, s.params()[i].name, s.get(i)),
+                _ => Ok(()),
+            };
+            c.text(row as u8 + 1, &line);
+        }
+        if self.page == Page::Params && p.params().is_empty() {
+            c.text(1, " no parameters");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::display::TextBuffer;
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+
+    fn rot(delta: i16) -> InputEvent {
+        InputEvent::Rotate {
+            id: 0,
+            delta,
+            shift: false,
+        }
+    }
+
+    fn btn(gesture: Gesture) -> InputEvent {
+        InputEvent::Button { id: 0, gesture }
+    }
+
+    struct Fixture {
+        menu: Menu,
+        p: Patterns,
+        s: Settings,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let mut menu = Menu::new();
+            menu.open();
+            Self {
+                menu,
+                p: Patterns::new(),
+                s: Settings::new(),
+            }
+        }
+        fn input(&mut self, evs: &[InputEvent]) {
+            for &ev in evs {
+                assert!(self.menu.input(ev, &mut self.p, &mut self.s));
+            }
+        }
+        fn rows(&self) -> Vec<String> {
+            let mut t = TextBuffer::new();
+            self.menu.draw(&mut t, &self.p, &self.s);
+            (0..ROWS).map(|i| t.row(i).to_string()).collect()
+        }
+    }
+
+    #[test]
+    fn closed_menu_passes_everything_through() {
+        let mut m = Menu::new();
+        let (mut p, mut s) = (Patterns::new(), Settings::new());
+        assert!(!m.input(btn(Gesture::Click), &mut p, &mut s));
+        let mut t = TextBuffer::new();
+        m.draw(&mut t, &p, &s);
+        assert_eq!(t.row(0), "");
+        m.open();
+        let cmd = InputEvent::Command(crate::input::Action::NextPattern);
+        assert!(!m.input(cmd, &mut p, &mut s));
+    }
+
+    #[test]
+    fn selects_a_pattern() {
+        let mut f = Fixture::new();
+        assert_eq!(f.rows(), ["menu", ">patterns", " parameters", " settings"]);
+        // The cursor stops at the ends of the list
+        f.input(&[rot(-1), btn(Gesture::Click)]);
+        assert_eq!(f.menu.page(), Page::Patterns);
+        assert_eq!(f.rows(), ["patterns", ">rainbow*", " breath", " zoom"]);
+        f.input(&[rot(5), btn(Gesture::Click)]);
+        assert_eq!(f.p.active(), 2);
+        assert_eq!(f.rows()[3], ">zoom*");
+        f.input(&[btn(Gesture::LongPress)]);
+        assert_eq!(f.menu.page(), Page::Main);
+        assert_eq!(f.rows()[1], ">patterns");
+        f.input(&[btn(Gesture::DoubleClick)]);
+        assert!(!f.menu.is_open());
+    }
+
+    #[test]
+    fn edits_a_parameter() {
+        let mut f = Fixture::new();
+        f.input(&[rot(1), btn(Gesture::Click)]);
+        assert_eq!(
+            f.rows(),
+            ["rainbow", ">speed 10", " saturation 255", " palette 0"]
+        );
+        f.input(&[rot(1), btn(Gesture::Click), rot(-10)]);
+        assert_eq!(f.menu.page(), Page::EditParam(1));
+        assert_eq!(f.rows()[1], "saturation: < 245 >");
+        // Held at the top of the range
+        f.input(&[rot(100)]);
+        assert_eq!(f.p.get(1), 255);
+        // Back on the list, where it left off
+        f.input(&[btn(Gesture::Click)]);
+        assert_eq!(f.menu.page(), Page::Params);
+        assert_eq!(f.rows()[2], ">saturation 255");
+        f.input(&[btn(Gesture::LongPress)]);
+        assert_eq!(f.menu.page(), Page::Main);
+        assert_eq!(f.rows()[2], ">parameters");
+    }
+
+    #[test]
+    fn scrolls_the_settings() {
+        let mut f = Fixture::new();
+        f.input(&[rot(2), btn(Gesture::Click), rot(5)]);
+        let rows = f.rows();
+        assert_eq!(rows[0], "settings");
+        assert!(rows[1].starts_with(" screen"), "{:?}", rows);
+        assert_eq!(rows[3], ">dmx address 1");
+        f.input(&[btn(Gesture::Click), rot(3), btn(Gesture::Click)]);
+        assert_eq!(f.s.dmx_address, 4);
+        assert_eq!(f.rows()[3], ">dmx address 4");
+    }
+}
@@ -0,0 +1,252 @@
+// Serial MIDI at 31250 baud. Running status is followed, real time bytes
+// may come between the bytes of any other message, and system exclusive
+// and other system messages are skipped.
+use crate::app::App;
+use crate::input::Action;
+use crate::m6::Render;
+use crate::preset::MAX_PARAMS;
+use crate::render::NAMES;
+
+pub const BAUD: u32 = 31_250;
+
+// Channels count from 0, shown as 1 to 16 by most gear
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Message {
+    NoteOn { channel: u8, note: u8, velocity: u8 },
+    NoteOff { channel: u8, note: u8, velocity: u8 },
+    ControlChange { channel: u8, control: u8, value: u8 },
+    ProgramChange { channel: u8, program: u8 },
+    // 24 per quarter note
+    Clock,
+    Start,
+    Continue,
+    Stop,
+}
+
+pub struct Parser {
+    // Channel message status, kept for running status
+    status: Option<u8>,
+    data: [u8; 2],
+    got: usize,
+}
+
+impl Parser {
+    pub const fn new() -> Self {
+        Self {
+            status: None,
+            data: [0; 2],
+            got: 0,
+        }
+    }
+    pub fn push(&mut self, b: u8) -> Option<Message> {
+        match b {
+            0xf8 => return Some(Message::Clock),
+            0xfa => return Some(Message::Start),
+            0xfb => return Some(Message::Continue),
+            0xfc => return Some(Message::Stop),
+            // Other real time bytes
+            0xf9..=0xff => return None,
+            // System exclusive and common, until the next status
+            0xf0..=0xf7 => self.status = None,
+            0x80..=0xef => {
+                self.status = Some(b);
+                self.got = 0;
+            }
+            _ => {
+                let status = self.status?;
+                self.data[self.got] = b;
+                self.got += 1;
+                let len = match status & 0xf0 {
+                    0xc0 | 0xd0 => 1,
+                    _ => 2,
+                };
+                if self.got == len {
+                    self.got = 0;
+                    return message(status, self.data);
+                }
+            }
+        }
+        None
+    }
+}
+
+fn message(status: u8, [a, b]: [u8; 2]) -> Option<Message> {
+    let channel = status & 0x0f;
+    let m = match status & 0xf0 {
+        0x90 if b > 0 => Message::NoteOn {
+            channel,
+            note: a,
+            velocity: b,
+        },
+        // Note on at velocity 0 is the usual note off
+        0x80 | 0x90 => Message::NoteOff {
+            channel,
+            note: a,
+            velocity: b,
+        },
+        0xb0 => Message::ControlChange {
+            channel,
+            control: a,
+            value: b,
+        },
+        0xc0 => Message::ProgramChange {
+            channel,
+            program: a,
+        },
+        // Aftertouch and pitch bend
+        _ => return None,
+    };
+    Some(m)
+}
+
+// Which messages drive the patterns: program changes select a pattern,
+// one controller the brightness and a run of controllers the active
+// pattern's parameters, each 0 to 127 across the whole range. Notes aren't
+// used yet.
+#[derive(Clone, Copy, Debug)]
+pub struct Map {
+    // None for every channel
+    pub channel: Option<u8>,
+    pub brightness: u8,
+    pub first_param: u8,
+}
+
+impl Map {
+    // Channel volume, then the undefined controllers from 20
+    pub const fn new() -> Self {
+        Self {
+            channel: None,
+            brightness: 7,
+            first_param: 20,
+        }
+    }
+    pub fn action(&self, msg: Message, app: &App) -> Option<Action> {
+        let on_channel = |ch: u8| self.channel.map_or(true, |c| c == ch);
+        match msg {
+            Message::ProgramChange { channel, program }
+                if on_channel(channel) && (program as usize) < NAMES.len() =>
+            {
+                Some(Action::Select(program))
+            }
+            Message::ControlChange {
+                channel,
+                control,
+                value,
+            } if on_channel(channel) => {
+                if control == self.brightness {
+                    let value = (value as i16) * 2 + (value as i16) / 64;
+                    return Some(Action::Setting { param: 0, value });
+                }
+                let i = control.checked_sub(self.first_param)? as usize;
+                let p = app.patterns.params().get(i).filter(|_| i < MAX_PARAMS)?;
+                let span = p.max as i32 - p.min as i32;
+                let value = p.min as i32 + (value as i32 * span + 63) / 127;
+                Some(Action::Set {
+                    param: i as u8,
+                    value: value as i16,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::input::{InputEvent, Mapping};
+    use std::vec::Vec;
+    use Message::*;
+
+    fn parse(bytes: &[u8]) -> Vec<Message> {
+        let mut p = Parser::new();
+        bytes.iter().filter_map(|&b| p.push(b)).collect()
+    }
+
+    #[test]
+    fn running_status() {
+        // A stray data byte before any status, then two notes on one status
+        let m = parse(&[0x40, 0x91, 60, 100, 61, 90, 0xc2, 7, 8]);
+        let on = |note, velocity| NoteOn {
+            channel: 1,
+            note,
+            velocity,
+        };
+        let program = |program| ProgramChange {
+            channel: 2,
+            program,
+        };
+        assert_eq!(m, [on(60, 100), on(61, 90), program(7), program(8)]);
+    }
+
+    #[test]
+    fn real_time_between_data_bytes() {
+        let m = parse(&[0xb0, 0xf8, 20, 0xfe, 64, 0xfa, 21, 0xfc, 0]);
+        let cc = |control, value| ControlChange {
+            channel: 0,
+            control,
+            value,
+        };
+        assert_eq!(m, [Clock, cc(20, 64), Start, Stop, cc(21, 0)]);
+    }
+
+    #[test]
+    fn system_messages_cancel_running_status() {
+        // Its data bytes aren't notes, nor is anything after until the
+        // next status
+        let m = parse(&[0x90, 60, 100, 0xf0, 0x7e, 60, 100, 0xf7, 61, 100]);
+        assert_eq!(m.len(), 1);
+        let m = parse(&[0x90, 60, 0xf2, 1, 2, 0x80, 60, 64]);
+        let off = NoteOff {
+            channel: 0,
+            note: 60,
+            velocity: 64,
+        };
+        assert_eq!(m, [off]);
+    }
+
+    #[test]
+    fn note_on_at_zero_velocity_is_off() {
+        let m = parse(&[0x9f, 60, 0]);
+        let off = NoteOff {
+            channel: 15,
+            note: 60,
+            velocity: 0,
+        };
+        assert_eq!(m, [off]);
+    }
+
+    #[test]
+    fn map_scales_to_the_ranges() {
+        let mut app = App::new(Mapping::new());
+        let map = Map::new();
+        let program = |program| ProgramChange {
+            channel: 3,
+            program,
+        };
+        assert_eq!(map.action(program(2), &app), Some(Action::Select(2)));
+        assert_eq!(map.action(program(NAMES.len() as u8), &app), None);
+        let cc = |control, value| ControlChange {
+            channel: 0,
+            control,
+            value,
+        };
+        let brightness = |value| Some(Action::Setting { param: 0, value });
+        assert_eq!(map.action(cc(7, 127), &app), brightness(255));
+        assert_eq!(map.action(cc(7, 0), &app), brightness(0));
+        app.input(InputEvent::Command(Action::Select(2)));
+        let p = app.patterns.params()[1];
+        let set = |value| Some(Action::Set { param: 1, value });
+        assert_eq!(map.action(cc(21, 127), &app), set(p.max));
+        assert_eq!(map.action(cc(21, 0), &app), set(p.min));
+        let past = map.first_param + app.patterns.params().len() as u8;
+        assert_eq!(map.action(cc(past, 0), &app), None);
+        let map = Map {
+            channel: Some(1),
+            ..Map::new()
+        };
+        assert_eq!(map.action(program(1), &app), None);
+    }
+}
@@ -0,0 +1,259 @@
+use smart_leds::RGB8;
+
+use crate::harmony::HARMONIES;
+use crate::hsv::{GAMMA, HSV, HUE_STEPS};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Stop {
+    pub pos: u8,
+    pub color: RGB8,
+}
+
+impl Stop {
+    pub const fn new(pos: u8, color: RGB8) -> Self {
+        Self { pos, color }
+    }
+}
+
+// Gradient stops, sorted by position
+#[derive(Clone, Copy, Debug)]
+pub struct Stops(&'static [Stop]);
+
+impl Stops {
+    // None unless the stops are sorted
+    pub fn new(stops: &'static [Stop]) -> Option<Self> {
+        if stops.windows(2).all(|w| w[0].pos <= w[1].pos) {
+            Some(Stops(stops))
+        } else {
+            None
+        }
+    }
+}
+
+// Modeled on FastLED's CRGBPalette16 / CRGBPalette256 / gradient palettes
+#[derive(Clone, Copy, Debug)]
+pub enum Palette {
+    Sixteen([RGB8; 16]),
+    Full(&'static [RGB8; 256]),
+    Gradient(Stops),
+}
+
+pub const fn hex(c: u32) -> RGB8 {
+    RGB8 {
+        r: (c >> 16) as u8,
+        g: (c >> 8) as u8,
+        b: c as u8,
+    }
+}
+
+pub fn blend(a: RGB8, b: RGB8, frac: u8) -> RGB8 {
+    let lerp = |x: u8, y: u8| -> u8 {
+        let d = (y as i32 - x as i32) * frac as i32;
+        (x as i32 + (d >> 8)) as u8
+    };
+    RGB8 {
+        r: lerp(a.r, b.r),
+        g: lerp(a.g, b.g),
+        b: lerp(a.b, b.b),
+    }
+}
+
+pub fn scale(c: RGB8, v: u8) -> RGB8 {
+    let s = |x: u8| -> u8 { ((x as u16 * (v as u16 + 1)) >> 8) as u8 };
+    RGB8 {
+        r: s(c.r),
+        g: s(c.g),
+        b: s(c.b),
+    }
+}
+
+impl Palette {
+    // Map a position on the HSV hue wheel onto a palette index
+    pub fn hue_index(h: u16) -> u8 {
+        ((h as u32 * 256) / HUE_STEPS as u32) as u8
+    }
+    pub fn sample(&self, idx: u8) -> RGB8 {
+        match self {
+            Palette::Sixteen(entries) => {
+                let hi = (idx >> 4) as usize;
+                let lo = idx & 0x0f;
+                blend(entries[hi], entries[(hi + 1) % 16], lo << 4)
+            }
+            Palette::Full(entries) => entries[idx as usize],
+            Palette::Gradient(Stops(stops)) => sample_gradient(stops, idx),
+        }
+    }
+    // Sample, scale by brightness and gamma correct, ready for the strip
+    pub fn color(&self, idx: u8, v: u8) -> RGB8 {
+        let c = scale(self.sample(idx), v);
+        RGB8 {
+            r: GAMMA[c.r as usize],
+            g: GAMMA[c.g as usize],
+            b: GAMMA[c.b as usize],
+        }
+    }
+}
+
+fn sample_gradient(stops: &[Stop], idx: u8) -> RGB8 {
+    let first = match stops.first() {
+        Some(s) => s,
+        None => return RGB8::default(),
+    };
+    if idx <= first.pos {
+        return first.color;
+    }
+    for w in stops.windows(2) {
+        let (a, b) = (w[0], w[1]);
+        // A stop's own position takes its color exactly
+        if idx < b.pos {
+            let span = (b.pos - a.pos) as u16;
+            let frac = ((idx - a.pos) as u16 * 255) / span;
+            return blend(a.color, b.color, frac as u8);
+        }
+    }
+    stops[stops.len() - 1].color
+}
+
+// Chosen by the patterns' "palette" parameter, from 1; 0 keeps their hues
+pub const PALETTES: [Palette; 5] = [PARTY, LAVA, OCEAN, FOREST, HEAT];
+// Highest "palette" value: the fixed palettes, then a harmony of each kind
+pub const CHOICES: i16 = (PALETTES.len() + HARMONIES.len()) as i16;
+
+// Harmonies are built around `seed`
+pub fn numbered(n: i16, seed: &HSV) -> Option<Palette> {
+    let i = (n as usize).checked_sub(1)?;
+    match PALETTES.get(i) {
+        Some(p) => Some(*p),
+        None => HARMONIES.get(i - PALETTES.len()).map(|h| h.palette(seed)),
+    }
+}
+
+pub const PARTY: Palette = Palette::Sixteen([
+    hex(0x5500AB),
+    hex(0x84007C),
+    hex(0xB5004B),
+    hex(0xE5001B),
+    hex(0xE81700),
+    hex(0xB84700),
+    hex(0xAB7700),
+    hex(0xABAB00),
+    hex(0xAB5500),
+    hex(0xDD2200),
+    hex(0xF2000E),
+    hex(0xC2003E),
+    hex(0x8F0071),
+    hex(0x5F00A1),
+    hex(0x2F00D0),
+    hex(0x0007F9),
+]);
+
+pub const LAVA: Palette = Palette::Sixteen([
+    hex(0x000000),
+    hex(0x800000),
+    hex(0x000000),
+    hex(0x800000),
+    hex(0x8B0000),
+    hex(0x8B0000),
+    hex(0x800000),
+    hex(0x8B0000),
+    hex(0x8B0000),
+    hex(0x8B0000),
+    hex(0xFF0000),
+    hex(0xFFA500),
+    hex(0xFFFFFF),
+    hex(0xFFA500),
+    hex(0xFF0000),
+    hex(0x8B0000),
+]);
+
+pub const OCEAN: Palette = Palette::Sixteen([
+    hex(0x191970),
+    hex(0x00008B),
+    hex(0x191970),
+    hex(0x000080),
+    hex(0x00008B),
+    hex(0x0000CD),
+    hex(0x2E8B57),
+    hex(0x008080),
+    hex(0x5F9EA0),
+    hex(0x0000FF),
+    hex(0x008B8B),
+    hex(0x6495ED),
+    hex(0x7FFFD4),
+    hex(0x2E8B57),
+    hex(0x00FFFF),
+    hex(0x87CEFA),
+]);
+
+pub const FOREST: Palette = Palette::Sixteen([
+    hex(0x006400),
+    hex(0x006400),
+    hex(0x556B2F),
+    hex(0x006400),
+    hex(0x008000),
+    hex(0x228B22),
+    hex(0x6B8E23),
+    hex(0x008000),
+    hex(0x2E8B57),
+    hex(0x66CDAA),
+    hex(0x32CD32),
+    hex(0x9ACD32),
+    hex(0x90EE90),
+    hex(0x7CFC00),
+    hex(0x66CDAA),
+    hex(0x228B22),
+]);
+
+const HEAT_STOPS: [Stop; 4] = [
+    Stop::new(0, hex(0x000000)),
+    Stop::new(128, hex(0xFF0000)),
+    Stop::new(224, hex(0xFFFF00)),
+    Stop::new(255, hex(0xFFFFFF)),
+];
+
+pub const HEAT: Palette = Palette::Gradient(Stops(&HEAT_STOPS));
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UNSORTED: [Stop; 2] = [Stop::new(200, hex(0xff0000)), Stop::new(100, hex(0x0000ff))];
+    const RAMP: [Stop; 2] = [Stop::new(64, hex(0x000000)), Stop::new(192, hex(0xfe0000))];
+
+    #[test]
+    fn unsorted_stops_are_refused() {
+        assert!(Stops::new(&UNSORTED).is_none());
+        assert!(Stops::new(&RAMP).is_some());
+        assert!(Stops::new(&HEAT_STOPS).is_some());
+    }
+
+    #[test]
+    fn gradient_holds_its_ends() {
+        let p = Palette::Gradient(Stops::new(&RAMP).unwrap());
+        assert_eq!(p.sample(0), hex(0x000000));
+        assert_eq!(p.sample(64), hex(0x000000));
+        assert_eq!(p.sample(128).r, 0x7e);
+        assert_eq!(p.sample(192), hex(0xfe0000));
+        assert_eq!(p.sample(255), hex(0xfe0000));
+    }
+
+    #[test]
+    fn numbered_from_one() {
+        let seed = HSV::new(0, 255, 255);
+        assert!(numbered(0, &seed).is_none());
+        assert!(numbered(-1, &seed).is_none());
+        assert!(numbered(CHOICES + 1, &seed).is_none());
+        match numbered(PALETTES.len() as i16, &seed) {
+            Some(Palette::Gradient(_)) => {}
+            p => panic!("{:?}", p),
+        }
+        // Then the harmonies, each starting from its first color
+        for (n, h) in (PALETTES.len() as i16 + 1..=CHOICES).zip(HARMONIES.iter()) {
+            let first = h.colors(&seed).next().unwrap().to_rgb_raw().into();
+            match numbered(n, &seed) {
+                Some(p @ Palette::Sixteen(_)) => assert_eq!(p.sample(0), first),
+                p => panic!("{}: {:?}", n, p),
+            }
+        }
+    }
+}
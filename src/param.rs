@@ -0,0 +1,50 @@
+#[derive(Clone, Copy, Debug)]
+pub struct Param {
+    pub name: &'static str,
+    pub min: i16,
+    pub max: i16,
+    // Change per step of a knob
+    pub step: i16,
+    // Wrap around instead of stopping at min/max, e.g. for hues
+    pub wrap: bool,
+}
+
+impl Param {
+    pub const fn new(name: &'static str, min: i16, max: i16, step: i16) -> Self {
+        let wrap = false;
+        Self {
+            name,
+            min,
+            max,
+            step,
+            wrap,
+        }
+    }
+    pub const fn wrapping(name: &'static str, min: i16, max: i16, step: i16) -> Self {
+        let wrap = true;
+        Self {
+            name,
+            min,
+            max,
+            step,
+            wrap,
+        }
+    }
+    pub fn clamp(&self, value: i32) -> i16 {
+        let (min, max) = (self.min as i32, self.max as i32);
+        let v = if self.wrap {
+            let span = max - min + 1;
+            (((value - min) % span) + span) % span + min
+        } else if value < min {
+            min
+        } else if value > max {
+            max
+        } else {
+            value
+        };
+        v as i16
+    }
+    pub fn adjusted(&self, value: i16, delta: i16) -> i16 {
+        self.clamp(value as i32 + (delta as i32 * self.step as i32))
+    }
+}
@@ -0,0 +1,32 @@
+use crate::m6::Render;
+use crate::render::Patterns;
+
+// Slots kept in RAM
+pub const PRESETS: usize = 4;
+// Most parameters any pattern has
+pub const MAX_PARAMS: usize = 8;
+
+// A pattern and the values of its parameters
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Preset {
+    pub pattern: u8,
+    pub values: [i16; MAX_PARAMS],
+}
+
+impl Preset {
+    pub fn capture(p: &Patterns) -> Self {
+        let pattern = p.active() as u8;
+        let mut values = [0; MAX_PARAMS];
+        for (i, v) in values.iter_mut().enumerate().take(p.params().len()) {
+            *v = p.get(i);
+        }
+        Self { pattern, values }
+    }
+    pub fn apply(&self, p: &mut Patterns) {
+        p.select(self.pattern as usize);
+        let params = p.params();
+        for (i, param) in params.iter().enumerate().take(MAX_PARAMS) {
+            p.set(i, param.clamp(self.values[i] as i32));
+        }
+    }
+}
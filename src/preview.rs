@@ -0,0 +1,62 @@
+use core::f32::consts::PI;
+
+use libm::F32Ext;
+use smart_leds::RGB8;
+
+use crate::display::{Canvas, HEIGHT, WIDTH};
+use crate::m6::{nodes, Node, Region, LEDS};
+
+// 4x4 ordered dither thresholds
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+// Side of the square drawn for each node, enough for all 17 shades
+const BLOCK: i16 = 4;
+
+// The fixture fills a square at the right edge, leaving the left for text
+const CX: i16 = (WIDTH - HEIGHT / 2) as i16;
+const CY: i16 = (HEIGHT / 2) as i16;
+
+fn radius(region: Region) -> f32 {
+    use Region::*;
+    match region {
+        Center => 0.0,
+        Inner => 6.0,
+        Ray => 10.0,
+        Outer => 13.0,
+    }
+}
+
+// Center of a node on screen; angles are fractions of a turn
+fn position(n: &Node) -> (i16, i16) {
+    let turn = *n.angle.numer() as f32 / *n.angle.denom() as f32;
+    let a = turn * 2.0 * PI;
+    let r = radius(n.region);
+    (
+        CX + (r * a.cos()).round() as i16,
+        CY + (r * a.sin()).round() as i16,
+    )
+}
+
+// Rough luma of the duty cycles, 0..=255
+fn level(c: &RGB8) -> u16 {
+    (c.r as u16 * 2 + c.g as u16 * 5 + c.b as u16) / 8
+}
+
+// Draws each node as a block shaded by the brighter of its two LEDs
+pub fn draw(c: &mut impl Canvas, leds: &[RGB8; LEDS]) {
+    for (n, pair) in nodes().iter().zip(leds.chunks(2)) {
+        let shade = (pair.iter().map(level).max().unwrap_or(0) * 16 / 255) as u8;
+        let (x, y) = position(n);
+        for dy in 0..BLOCK {
+            for dx in 0..BLOCK {
+                let px = x - BLOCK / 2 + dx;
+                let py = y - BLOCK / 2 + dy;
+                if px < 0 || py < 0 || px >= WIDTH as i16 || py >= HEIGHT as i16 {
+                    continue;
+                }
+                let threshold = BAYER[py as usize % 4][px as usize % 4];
+                c.pixel(px as u8, py as u8, shade > threshold);
+            }
+        }
+    }
+}
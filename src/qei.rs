@@ -0,0 +1,38 @@
+use stm32f1xx_hal::pac::{TIM2, TIM3, TIM4};
+
+use crate::knob::Counter;
+
+// Put a general purpose timer into encoder mode 3, counting every edge of
+// CH1 and CH2 (four per quadrature cycle). The timer's clock and the CH1/CH2
+// pins must already be enabled and configured as inputs.
+pub trait EncoderMode: Counter + Sized {
+    fn encoder_mode(self) -> Self;
+}
+
+macro_rules! encoder_mode {
+    ($($TIM:ident,)+) => {
+        $(
+            impl Counter for $TIM {
+                fn count(&self) -> u16 {
+                    self.cnt.read().cnt().bits()
+                }
+            }
+
+            impl EncoderMode for $TIM {
+                fn encoder_mode(self) -> Self {
+                    self.cr1.modify(|_r, w| w.cen().clear_bit());
+                    // TI1 and TI2 mapped straight onto IC1 and IC2
+                    self.ccmr1_input()
+                        .write(|w| unsafe { w.cc1s().bits(0b01).cc2s().bits(0b01) });
+                    self.ccer.write(|w| w.cc1e().set_bit().cc2e().set_bit());
+                    self.smcr.write(|w| unsafe { w.sms().bits(0b011) });
+                    self.arr.write(|w| w.arr().bits(0xffff));
+                    self.cr1.modify(|_r, w| w.cen().set_bit());
+                    self
+                }
+            }
+        )+
+    };
+}
+
+encoder_mode!(TIM2, TIM3, TIM4,);
@@ -0,0 +1,520 @@
+use core::fmt;
+use core::str::FromStr;
+
+use crate::app::App;
+use crate::button::Gesture;
+use crate::input::{Action, InputEvent};
+use crate::preset::{Preset, MAX_PARAMS, PRESETS};
+use crate::render::NAMES;
+use crate::settings;
+
+// Events kept for dumping; older ones are overwritten
+pub const RECORD_LEN: usize = 64;
+// Records between snapshots. Two are kept, so whatever has been
+// overwritten, a dump keeps at least this many records after one.
+pub const CHECKPOINT: usize = RECORD_LEN / 2;
+
+// First line of a dump. Replays start from boot, or from the snapshot on
+// the line after; a dump that lost records with no snapshot to start
+// from, or had them overwritten while it printed, can't be replayed and
+// says so instead.
+pub const HEADER: &str = "# glow recording";
+pub const TRUNCATED: &str = "# glow recording truncated";
+// Starts the snapshot line
+pub const STATE: &str = "state";
+
+// An input event and the frame it was applied before. Frames count from
+// boot, so replaying every record against fresh patterns reproduces the
+// exact output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Record {
+    pub frame: u32,
+    pub event: InputEvent,
+}
+
+// What input can change and a replay needs to carry on from: the settings,
+// every pattern's parameters, the one showing, the presets and power.
+// Animation phase, timers and an open menu aren't kept, so a replay from
+// anywhere but boot may drift until the patterns next restart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Snapshot {
+    pub frame: u32,
+    on: bool,
+    settings: [i16; settings::COUNT],
+    active: u8,
+    patterns: [[i16; MAX_PARAMS]; NAMES.len()],
+    presets: [Option<Preset>; PRESETS],
+}
+
+impl Snapshot {
+    pub fn capture(app: &App, frame: u32) -> Self {
+        let mut settings = [0; settings::COUNT];
+        for (i, v) in settings.iter_mut().enumerate() {
+            *v = app.settings.get(i);
+        }
+        let mut patterns = [[0; MAX_PARAMS]; NAMES.len()];
+        for (idx, values) in patterns.iter_mut().enumerate() {
+            if let Some(r) = app.patterns.pattern(idx) {
+                for (i, v) in values.iter_mut().enumerate().take(r.params().len()) {
+                    *v = r.get(i);
+                }
+            }
+        }
+        let mut presets = [None; PRESETS];
+        for (slot, p) in presets.iter_mut().enumerate() {
+            *p = app.preset(slot as u8);
+        }
+        Self {
+            frame,
+            on: app.is_on(),
+            settings,
+            active: app.patterns.active() as u8,
+            patterns,
+            presets,
+        }
+    }
+    pub fn apply(&self, app: &mut App) {
+        for (i, &v) in self.settings.iter().enumerate() {
+            app.settings.set(i, v);
+        }
+        for (idx, &values) in self.patterns.iter().enumerate() {
+            let pattern = idx as u8;
+            Preset { pattern, values }.apply(&mut app.patterns);
+        }
+        app.patterns.select(self.active as usize);
+        for (slot, p) in self.presets.iter().enumerate() {
+            if let Some(p) = p {
+                app.set_preset(slot as u8, *p);
+            }
+        }
+        app.set_on(self.on);
+    }
+}
+
+// Records are numbered from boot; record n is kept in slot n % RECORD_LEN
+pub struct Recorder {
+    frame: u32,
+    records: [Option<Record>; RECORD_LEN],
+    recorded: u32,
+    // The newest two, with the number of the record each was taken before
+    snapshots: [Option<(u32, Snapshot)>; 2],
+}
+
+impl Recorder {
+    pub const fn new() -> Self {
+        Self {
+            frame: 0,
+            records: [None; RECORD_LEN],
+            recorded: 0,
+            snapshots: [None, None],
+        }
+    }
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+    // Call once each frame has been rendered
+    pub fn next_frame(&mut self) {
+        self.frame = self.frame.wrapping_add(1);
+    }
+    // Call before the app takes the event
+    pub fn record(&mut self, event: InputEvent, app: &App) {
+        if self.recorded > 0 && self.recorded as usize % CHECKPOINT == 0 {
+            self.checkpoint(app);
+        }
+        self.records[self.recorded as usize % RECORD_LEN] = Some(Record {
+            frame: self.frame,
+            event,
+        });
+        self.recorded = self.recorded.wrapping_add(1);
+    }
+    // Snapshots the app as the next record will find it. `record` does so
+    // as needed; call it at boot too if the app didn't start out fresh.
+    pub fn checkpoint(&mut self, app: &App) {
+        let slot = self.recorded as usize / CHECKPOINT % 2;
+        self.snapshots[slot] = Some((self.recorded, Snapshot::capture(app, self.frame)));
+    }
+    // Numbers of the first record a dump starts from and one past the
+    // newest. That's record 0 until any are overwritten, then the oldest
+    // snapshot's, so the first is also how many are left out.
+    pub fn span(&self) -> (u32, u32) {
+        let kept = (self.recorded as usize).min(RECORD_LEN) as u32;
+        let oldest = self.recorded - kept;
+        if oldest == 0 {
+            return (0, self.recorded);
+        }
+        let first = self
+            .snapshots
+            .iter()
+            .flatten()
+            .map(|&(n, _)| n)
+            .filter(|&n| n >= oldest)
+            .min()
+            .unwrap_or(oldest);
+        (first, self.recorded)
+    }
+    // The snapshot taken before record n, if still kept
+    pub fn snapshot(&self, n: u32) -> Option<Snapshot> {
+        self.snapshots
+            .iter()
+            .flatten()
+            .find(|&&(m, _)| m == n)
+            .map(|&(_, s)| s)
+    }
+    // None once overwritten, so a dump can go a record at a time while
+    // recording carries on
+    pub fn get(&self, n: u32) -> Option<Record> {
+        let (first, end) = self.span();
+        if n < first || n >= end {
+            return None;
+        }
+        self.records[n as usize % RECORD_LEN]
+    }
+}
+
+// The lines a dump starts with, given the first record it holds and the
+// snapshot taken before that one. Without a snapshot, a dump that doesn't
+// start at record 0 says how many it left out.
+pub fn write_header(
+    w: &mut impl fmt::Write,
+    first: u32,
+    snapshot: Option<&Snapshot>,
+) -> fmt::Result {
+    match snapshot {
+        Some(s) => write!(w, "{}\r\n{}\r\n", HEADER, s),
+        None if first == 0 => write!(w, "{}\r\n", HEADER),
+        None => write!(w, "{}, {} events lost\r\n", TRUNCATED, first),
+    }
+}
+
+// As one line: the frame, power, the active pattern, the settings, each
+// pattern's values, then each preset as its pattern and values or `-`
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {} {}",
+            STATE, self.frame, self.on as u8, self.active
+        )?;
+        for v in self.settings.iter().chain(self.patterns.iter().flatten()) {
+            write!(f, " {}", v)?;
+        }
+        for p in self.presets.iter() {
+            match p {
+                Some(p) => {
+                    write!(f, " {}", p.pattern)?;
+                    for v in p.values.iter() {
+                        write!(f, " {}", v)?;
+                    }
+                }
+                None => write!(f, " -")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        let mut words = s.split_whitespace();
+        if words.next() != Some(STATE) {
+            return Err(ParseError);
+        }
+        let mut next = || words.next().ok_or(ParseError);
+        fn num<T: FromStr>(w: Result<&str, ParseError>) -> Result<T, ParseError> {
+            w?.parse().map_err(|_| ParseError)
+        }
+        let frame = num(next())?;
+        let on = num::<u8>(next())? != 0;
+        let active = num(next())?;
+        let mut settings = [0; settings::COUNT];
+        for v in settings.iter_mut() {
+            *v = num(next())?;
+        }
+        let mut patterns = [[0; MAX_PARAMS]; NAMES.len()];
+        for v in patterns.iter_mut().flatten() {
+            *v = num(next())?;
+        }
+        let mut presets = [None; PRESETS];
+        for p in presets.iter_mut() {
+            let w = next()?;
+            if w == "-" {
+                continue;
+            }
+            let mut values = [0; MAX_PARAMS];
+            for v in values.iter_mut() {
+                *v = num(next())?;
+            }
+            let pattern = num(Ok(w))?;
+            *p = Some(Preset { pattern, values });
+        }
+        if next().is_ok() {
+            return Err(ParseError);
+        }
+        Ok(Snapshot {
+            frame,
+            on,
+            settings,
+            active,
+            patterns,
+            presets,
+        })
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ", self.frame)?;
+        match self.event {
+            InputEvent::Rotate { id, delta, shift } => {
+                write!(f, "rot {} {} {}", id, delta, shift as u8)
+            }
+            InputEvent::Button { id, gesture } => {
+                write!(f, "btn {} ", id)?;
+                match gesture {
+                    Gesture::Click => write!(f, "click"),
+                    Gesture::DoubleClick => write!(f, "double"),
+                    Gesture::LongPress => write!(f, "long"),
+                    Gesture::HoldTurn(d) => write!(f, "hold {}", d),
+                }
+            }
+            InputEvent::Command(action) => {
+                write!(f, "cmd ")?;
+                match action {
+                    Action::Adjust { param, delta } => write!(f, "adj {} {}", param, delta),
+                    Action::Turn { bank, slot, delta } => {
+                        write!(f, "turn {} {} {}", bank, slot, delta)
+                    }
+                    Action::Set { param, value } => write!(f, "set {} {}", param, value),
+                    Action::Setting { param, value } => {
+                        write!(f, "setting {} {}", param, value)
+                    }
+                    Action::AdjustSetting { param, delta } => {
+                        write!(f, "adjset {} {}", param, delta)
+                    }
+                    Action::Select(n) => write!(f, "sel {}", n),
+                    Action::Save(n) => write!(f, "save {}", n),
+                    Action::Load(n) => write!(f, "load {}", n),
+                    Action::NextPattern => write!(f, "next"),
+                    Action::PrevPattern => write!(f, "prev"),
+                    Action::Dump => write!(f, "dump"),
+                    Action::Menu => write!(f, "menu"),
+                    Action::Power => write!(f, "power"),
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParseError;
+
+impl FromStr for Record {
+    type Err = ParseError;
+    fn from_str(s: &str) -> Result<Self, ParseError> {
+        let mut words = s.split_whitespace();
+        let mut next = || words.next().ok_or(ParseError);
+        fn num<T: FromStr>(w: Result<&str, ParseError>) -> Result<T, ParseError> {
+            w?.parse().map_err(|_| ParseError)
+        }
+        let frame = num(next())?;
+        let event = match next()? {
+            "rot" => InputEvent::Rotate {
+                id: num(next())?,
+                delta: num(next())?,
+                shift: num::<u8>(next())? != 0,
+            },
+            "btn" => {
+                let id = num(next())?;
+                let gesture = match next()? {
+                    "click" => Gesture::Click,
+                    "double" => Gesture::DoubleClick,
+                    "long" => Gesture::LongPress,
+                    "hold" => Gesture::HoldTurn(num(next())?),
+                    _ => return Err(ParseError),
+                };
+                InputEvent::Button { id, gesture }
+            }
+            "cmd" => InputEvent::Command(match next()? {
+                "adj" => Action::Adjust {
+                    param: num(next())?,
+                    delta: num(next())?,
+                },
+                "turn" => Action::Turn {
+                    bank: num(next())?,
+                    slot: num(next())?,
+                    delta: num(next())?,
+                },
+                "set" => Action::Set {
+                    param: num(next())?,
+                    value: num(next())?,
+                },
+                "setting" => Action::Setting {
+                    param: num(next())?,
+                    value: num(next())?,
+                },
+                "adjset" => Action::AdjustSetting {
+                    param: num(next())?,
+                    delta: num(next())?,
+                },
+                "sel" => Action::Select(num(next())?),
+                "save" => Action::Save(num(next())?),
+                "load" => Action::Load(num(next())?),
+                "next" => Action::NextPattern,
+                "prev" => Action::PrevPattern,
+                "dump" => Action::Dump,
+                "menu" => Action::Menu,
+                "power" => Action::Power,
+                _ => return Err(ParseError),
+            }),
+            _ => return Err(ParseError),
+        };
+        Ok(Record { frame, event })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Mapping;
+    use core::fmt::Write;
+
+    fn rot(delta: i16) -> InputEvent {
+        InputEvent::Rotate {
+            id: 0,
+            delta,
+            shift: false,
+        }
+    }
+
+    #[test]
+    fn keeps_the_newest() {
+        let app = App::new(Mapping::new());
+        let mut rec = Recorder::new();
+        assert_eq!(rec.span(), (0, 0));
+        assert_eq!(rec.get(0), None);
+        for i in 0..RECORD_LEN as i16 + 10 {
+            rec.record(rot(i), &app);
+            rec.next_frame();
+        }
+        // Records 10 on are kept, but a dump starts at the first snapshot
+        // after them
+        let (first, end) = rec.span();
+        assert_eq!((first, end), (CHECKPOINT as u32, RECORD_LEN as u32 + 10));
+        assert_eq!(rec.get(first - 1), None);
+        assert_eq!(rec.get(end), None);
+        assert_eq!(
+            rec.get(first).map(|r| (r.frame, r.event)),
+            Some((first, rot(first as i16)))
+        );
+        assert_eq!(rec.get(end - 1).map(|r| r.frame), Some(end - 1));
+        assert_eq!(rec.snapshot(first).map(|s| s.frame), Some(first));
+        assert_eq!(rec.snapshot(0), None);
+    }
+
+    #[test]
+    fn snapshots_restore_the_app() {
+        let mut app = App::new(Mapping::new());
+        let mut rec = Recorder::new();
+        let events = [
+            InputEvent::Command(Action::Setting {
+                param: 0,
+                value: 90,
+            }),
+            InputEvent::Command(Action::Set { param: 0, value: 7 }),
+            InputEvent::Command(Action::Save(2)),
+            InputEvent::Command(Action::NextPattern),
+            InputEvent::Command(Action::Set { param: 1, value: 3 }),
+            InputEvent::Command(Action::Power),
+        ];
+        // Every record after the first two rounds of snapshots is one of
+        // these, so the dump starts right after a change
+        for i in 0..RECORD_LEN * 2 {
+            let ev = events[i % events.len()];
+            rec.record(ev, &app);
+            let _ = app.input(ev);
+            rec.next_frame();
+        }
+        let (first, end) = rec.span();
+        let snap = rec.snapshot(first).unwrap();
+
+        // Through its line, as glow-replay gets it
+        let mut line: heapless::String<heapless::consts::U512> = heapless::String::new();
+        write!(line, "{}", snap).unwrap();
+        let snap: Snapshot = line.parse().unwrap();
+        let mut b = App::new(Mapping::new());
+        snap.apply(&mut b);
+        for n in first..end {
+            let _ = b.input(rec.get(n).unwrap().event);
+        }
+        assert_eq!(Snapshot::capture(&b, 0), Snapshot::capture(&app, 0));
+        assert_eq!(b.settings.brightness, 90);
+        assert_eq!(b.preset(2), app.preset(2));
+        assert!(b.preset(2).is_some());
+
+        assert_eq!("state 1 1".parse::<Snapshot>(), Err(ParseError));
+        line.push_str(" 3").unwrap();
+        assert_eq!(line.parse::<Snapshot>(), Err(ParseError));
+    }
+
+    #[test]
+    fn header_says_when_truncated() {
+        let mut s: heapless::String<heapless::consts::U64> = heapless::String::new();
+        write_header(&mut s, 0, None).unwrap();
+        assert_eq!(s.as_str(), "# glow recording\r\n");
+        let mut s: heapless::String<heapless::consts::U64> = heapless::String::new();
+        write_header(&mut s, 3, None).unwrap();
+        assert!(s.starts_with(TRUNCATED));
+        assert!(!HEADER.starts_with(TRUNCATED));
+
+        let snap = Snapshot::capture(&App::new(Mapping::new()), 40);
+        let mut s: heapless::String<heapless::consts::U512> = heapless::String::new();
+        write_header(&mut s, 3, Some(&snap)).unwrap();
+        let mut lines = s.lines();
+        assert_eq!(lines.next(), Some(HEADER));
+        assert_eq!(lines.next().map(|l| l.parse()), Some(Ok(snap)));
+    }
+
+    #[test]
+    fn lines_round_trip() {
+        let events = [
+            rot(-3),
+            InputEvent::Rotate {
+                id: 1,
+                delta: 2,
+                shift: true,
+            },
+            InputEvent::Button {
+                id: 1,
+                gesture: Gesture::HoldTurn(-4),
+            },
+            InputEvent::Button {
+                id: 0,
+                gesture: Gesture::DoubleClick,
+            },
+            InputEvent::Command(Action::Turn {
+                bank: 1,
+                slot: 0,
+                delta: 5,
+            }),
+            InputEvent::Command(Action::Setting { param: 3, value: 2 }),
+            InputEvent::Command(Action::AdjustSetting {
+                param: 0,
+                delta: -1,
+            }),
+            InputEvent::Command(Action::Load(2)),
+            InputEvent::Command(Action::Power),
+        ];
+        for (i, &event) in events.iter().enumerate() {
+            let r = Record {
+                frame: i as u32 * 1000,
+                event,
+            };
+            let mut s: heapless::String<heapless::consts::U64> = heapless::String::new();
+            write!(s, "{}", r).unwrap();
+            assert_eq!(s.parse(), Ok(r), "{}", s);
+        }
+        assert_eq!("12 cmd nope".parse::<Record>(), Err(ParseError));
+        assert_eq!("12 rot 0 1".parse::<Record>(), Err(ParseError));
+    }
+}
@@ -0,0 +1,143 @@
+use glow_proto::{Request, Response, Status};
+
+use crate::app::App;
+use crate::input::Action;
+use crate::m6::{Render, LEDS};
+use crate::param::Param;
+use crate::render::NAMES;
+
+// What to do about a request from the binary protocol, see glow-proto
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reply {
+    Answer(Response<'static>),
+    // Dispatch like any other input, then acknowledge
+    Dispatch(Action),
+    // Hand the frame to App::push_frame; only for a frame of every LED
+    Push,
+    // Answered by the firmware, which knows the state of the hardware
+    Diag,
+}
+
+fn param(index: u8, p: Option<&Param>, value: i16) -> Reply {
+    match p {
+        Some(p) => Reply::Answer(Response::Param {
+            index,
+            name: p.name,
+            min: p.min,
+            max: p.max,
+            step: p.step,
+            value,
+        }),
+        None => Reply::Answer(Response::Nack(Status::OutOfRange)),
+    }
+}
+
+pub fn handle(req: &Request, app: &App) -> Reply {
+    let p = &app.patterns;
+    let s = &app.settings;
+    let out_of_range = Reply::Answer(Response::Nack(Status::OutOfRange));
+    match *req {
+        Request::Ping => Reply::Answer(Response::Ack),
+        Request::GetState => Reply::Answer(Response::State {
+            pattern: p.active() as u8,
+            patterns: NAMES.len() as u8,
+            params: p.params().len() as u8,
+            settings: s.params().len() as u8,
+            streaming: app.is_streaming(),
+        }),
+        Request::GetPattern(n) => match p.pattern(n as usize) {
+            Some(r) => Reply::Answer(Response::Pattern {
+                index: n,
+                name: NAMES[n as usize],
+                params: r.params().len() as u8,
+            }),
+            None => out_of_range,
+        },
+        Request::GetParam(n) => param(n, p.params().get(n as usize), p.get(n as usize)),
+        Request::GetSetting(n) => param(n, s.params().get(n as usize), s.get(n as usize)),
+        Request::Select(n) if (n as usize) < NAMES.len() => Reply::Dispatch(Action::Select(n)),
+        Request::SetParam { param, value } if (param as usize) < p.params().len() => {
+            Reply::Dispatch(Action::Set { param, value })
+        }
+        Request::SetSetting { param, value } if (param as usize) < s.params().len() => {
+            Reply::Dispatch(Action::Setting { param, value })
+        }
+        Request::Select(_) | Request::SetParam { .. } | Request::SetSetting { .. } => out_of_range,
+        Request::PushFrame(rgb) if rgb.len() == LEDS * 3 => Reply::Push,
+        Request::PushFrame(_) => Reply::Answer(Response::Nack(Status::BadRequest)),
+        Request::GetDiag => Reply::Diag,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Mapping;
+
+    fn nack(status: Status) -> Reply {
+        Reply::Answer(Response::Nack(status))
+    }
+
+    #[test]
+    fn out_of_range() {
+        let app = App::new(Mapping::new());
+        let patterns = NAMES.len() as u8;
+        let params = app.patterns.params().len() as u8;
+        let settings = app.settings.params().len() as u8;
+        let range = nack(Status::OutOfRange);
+
+        assert_eq!(
+            handle(&Request::Select(patterns - 1), &app),
+            Reply::Dispatch(Action::Select(patterns - 1))
+        );
+        assert_eq!(handle(&Request::Select(patterns), &app), range);
+        assert_eq!(handle(&Request::GetPattern(patterns), &app), range);
+
+        match handle(&Request::GetParam(params - 1), &app) {
+            Reply::Answer(Response::Param { index, .. }) => assert_eq!(index, params - 1),
+            r => panic!("{:?}", r),
+        }
+        assert_eq!(handle(&Request::GetParam(params), &app), range);
+        let set = Request::SetParam {
+            param: params,
+            value: 1,
+        };
+        assert_eq!(handle(&set, &app), range);
+
+        let set = Request::SetSetting {
+            param: settings - 1,
+            value: 1,
+        };
+        assert_eq!(
+            handle(&set, &app),
+            Reply::Dispatch(Action::Setting {
+                param: settings - 1,
+                value: 1
+            })
+        );
+        let set = Request::SetSetting {
+            param: settings,
+            value: 1,
+        };
+        assert_eq!(handle(&set, &app), range);
+        assert_eq!(handle(&Request::GetSetting(settings), &app), range);
+    }
+
+    #[test]
+    fn frames_must_cover_the_strip() {
+        let app = App::new(Mapping::new());
+        let rgb = [0; LEDS * 3 + 3];
+        assert_eq!(
+            handle(&Request::PushFrame(&rgb[..LEDS * 3]), &app),
+            Reply::Push
+        );
+        for &n in [0, LEDS * 3 - 1, LEDS * 3 + 3].iter() {
+            assert_eq!(
+                handle(&Request::PushFrame(&rgb[..n]), &app),
+                nack(Status::BadRequest),
+                "{} bytes",
+                n
+            );
+        }
+    }
+}
@@ -2,10 +2,11 @@ use core::fmt::Write;
 
 use heapless::{consts, String, Vec};
 use libm::F32Ext;
+use smart_leds::RGB8;
 
-use crate::hsv::HSV;
-use crate::knob::Direction;
+use crate::hsv::{HSV, HUE_MAX};
 use crate::m6::{Node, Region, Render};
+use crate::param::Param;
 
 pub struct Breath {
     hue: i16,
@@ -35,8 +36,15 @@ fn breathe(x: f32) -> f32 {
     ((x.sin().exp() - (1.0 / E)) * scale)
 }
 
+const PARAMS: [Param; 3] = [
+    Param::wrapping("hue", 0, HUE_MAX, 8),
+    Param::new("scale", -512, 512, 8),
+    // In hundredths of a radian per tick
+    Param::new("speed", 1, 100, 1),
+];
+
 impl Render for Breath {
-    fn render(&self, n: &Node) -> (HSV, HSV) {
+    fn render(&self, n: &Node) -> (RGB8, RGB8) {
         use Region::*;
         let (vma, vmb): (f32, f32) = match n.region {
             Center => (1.0, 1.0),
@@ -58,7 +66,7 @@ impl Render for Breath {
             0xa0,
             128 + (size * vmb * b) as u8,
         );
-        (a, b)
+        (a.into(), b.into())
     }
     fn tick(&mut self) {
         self.phase += self.speed;
@@ -78,26 +86,23 @@ impl Render for Breath {
         rv
     }
 
-    fn knob1(&mut self, dir: Direction) {
-        use Direction::*;
-        match dir {
-            CW => {
-                self.hue += 8;
-            }
-            CCW => {
-                self.hue -= 8;
-            }
+    fn params(&self) -> &'static [Param] {
+        &PARAMS
+    }
+    fn get(&self, param: usize) -> i16 {
+        match param {
+            0 => self.hue,
+            1 => self.scale as i16,
+            2 => (self.speed * 100.0).round() as i16,
+            _ => 0,
         }
     }
-    fn knob2(&mut self, dir: Direction) {
-        use Direction::*;
-        match dir {
-            CW => {
-                self.scale += 8.0;
-            }
-            CCW => {
-                self.scale -= 8.0;
-            }
+    fn set(&mut self, param: usize, value: i16) {
+        match param {
+            0 => self.hue = value,
+            1 => self.scale = value as f32,
+            2 => self.speed = value as f32 / 100.0,
+            _ => {}
         }
     }
 }
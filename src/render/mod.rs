@@ -5,3 +5,137 @@ pub mod zoom;
 pub use breath::Breath;
 pub use rainbow::Rainbow;
 pub use zoom::Zoom;
+
+use core::fmt::Write;
+
+use heapless::{consts, String, Vec};
+use smart_leds::RGB8;
+
+use crate::input::{Action, BANK_SIZE};
+use crate::m6::{Node, Render};
+use crate::param::Param;
+
+pub const NAMES: [&str; 3] = ["rainbow", "breath", "zoom"];
+
+// All the patterns, rendering through whichever one is active
+pub struct Patterns {
+    active: usize,
+    rainbow: Rainbow,
+    breath: Breath,
+    zoom: Zoom,
+}
+
+impl Patterns {
+    pub const fn new() -> Self {
+        let active = 0;
+        let rainbow = Rainbow::new();
+        let breath = Breath::new();
+        let zoom = Zoom::new();
+        Self {
+            active,
+            rainbow,
+            breath,
+            zoom,
+        }
+    }
+    pub fn active(&self) -> usize {
+        self.active
+    }
+    pub fn name(&self) -> &'static str {
+        NAMES[self.active]
+    }
+    pub fn select(&mut self, idx: usize) {
+        if idx < NAMES.len() {
+            self.active = idx;
+        }
+    }
+    pub fn current(&self) -> &dyn Render {
+        match self.active {
+            1 => &self.breath,
+            2 => &self.zoom,
+            _ => &self.rainbow,
+        }
+    }
+    // Any pattern by index, e.g. to list its parameters
+    pub fn pattern(&self, idx: usize) -> Option<&dyn Render> {
+        match idx {
+            0 => Some(&self.rainbow),
+            1 => Some(&self.breath),
+            2 => Some(&self.zoom),
+            _ => None,
+        }
+    }
+    pub fn current_mut(&mut self) -> &mut dyn Render {
+        match self.active {
+            1 => &mut self.breath,
+            2 => &mut self.zoom,
+            _ => &mut self.rainbow,
+        }
+    }
+    pub fn handle(&mut self, action: Action) {
+        match action {
+            Action::Adjust { param, delta } => self.adjust(param as usize, delta),
+            Action::Turn { bank, slot, delta } => {
+                if let Some(param) = self.knob_param(bank, slot) {
+                    self.adjust(param, delta);
+                }
+            }
+            Action::Set { param, value } => {
+                if let Some(p) = self.params().get(param as usize) {
+                    let v = p.clamp(value as i32);
+                    self.set(param as usize, v);
+                }
+            }
+            Action::Select(idx) => self.select(idx as usize),
+            Action::NextPattern => self.active = (self.active + 1) % NAMES.len(),
+            Action::PrevPattern => self.active = (self.active + NAMES.len() - 1) % NAMES.len(),
+            // Handled by the app
+            Action::Setting { .. }
+            | Action::AdjustSetting { .. }
+            | Action::Save(_)
+            | Action::Load(_)
+            | Action::Dump
+            | Action::Menu
+            | Action::Power => {}
+        }
+    }
+    // Bank number and the parameters its knob slots address, e.g.
+    // "2 hue|scale"
+    pub fn bank_label(&self, bank: u8) -> String<consts::U32> {
+        let mut rv = String::new();
+        let _ = write!(rv, "{}", bank + 1);
+        for slot in 0..(BANK_SIZE as u8) {
+            let name = self
+                .knob_param(bank, slot)
+                .and_then(|i| self.params().get(i))
+                .map_or("-", |p| p.name);
+            let sep = if slot == 0 { ' ' } else { '|' };
+            let _ = write!(rv, "{}{}", sep, name);
+        }
+        rv
+    }
+}
+
+impl Render for Patterns {
+    fn render(&self, n: &Node) -> (RGB8, RGB8) {
+        self.current().render(n)
+    }
+    fn tick(&mut self) {
+        self.current_mut().tick()
+    }
+    fn debug(&self) -> Vec<String<consts::U16>, consts::U8> {
+        self.current().debug()
+    }
+    fn params(&self) -> &'static [Param] {
+        self.current().params()
+    }
+    fn get(&self, param: usize) -> i16 {
+        self.current().get(param)
+    }
+    fn set(&mut self, param: usize, value: i16) {
+        self.current_mut().set(param, value)
+    }
+    fn knob_param(&self, bank: u8, slot: u8) -> Option<usize> {
+        self.current().knob_param(bank, slot)
+    }
+}
@@ -1,15 +1,22 @@
 use core::{fmt::Write, ops::Add, ops::Mul};
 
 use heapless::{consts, String, Vec};
+use smart_leds::RGB8;
 
-use crate::hsv::{HSV, HUE_MAX};
-use crate::knob::Direction;
+use crate::hsv::{HueMap, HSV, HUE_STEPS};
 use crate::m6::{Node, Region, Render};
+use crate::palette::{self, Palette};
+use crate::param::Param;
 
 pub struct Rainbow {
     offset: i16,
     speed: i16,
     saturation: u8,
+    // See `palette::numbered`
+    palette: i16,
+    // Its colors, kept so harmonies aren't built for every LED
+    colors: Option<Palette>,
+    hue_map: HueMap,
 }
 
 impl Rainbow {
@@ -17,31 +24,58 @@ impl Rainbow {
         let offset = 0;
         let speed = 10;
         let saturation = 0xff;
+        let palette = 0;
+        let colors = None;
+        let hue_map = HueMap::Spectrum;
         Self {
             offset,
             speed,
             saturation,
+            palette,
+            colors,
+            hue_map,
+        }
+    }
+    // Harmonies start from red at the pattern's saturation, as the offset
+    // turns the wheel through them anyway
+    fn choose_colors(&mut self) {
+        let seed = HSV::new(0, self.saturation, 0xff);
+        self.colors = palette::numbered(self.palette, &seed);
+    }
+    fn color(&self, hue: i16) -> RGB8 {
+        let c = HSV::new(self.offset + hue, self.saturation, 0x80);
+        match self.colors {
+            Some(p) => p.color(Palette::hue_index(c.h), c.v),
+            None => c.to_rgb_mapped(self.hue_map).into(),
         }
     }
 }
 
+const PARAMS: [Param; 4] = [
+    Param::new("speed", -128, 128, 1),
+    Param::new("saturation", 0, 255, 1),
+    Param::wrapping("palette", 0, palette::CHOICES, 1),
+    // See `HueMap`, for when there's no palette
+    Param::wrapping("hue map", 0, 1, 1),
+];
+
 impl Render for Rainbow {
-    fn render(&self, n: &Node) -> (HSV, HSV) {
+    fn render(&self, n: &Node) -> (RGB8, RGB8) {
         use num_rational::Ratio;
         use Region::*;
         let (ao, bo): (Ratio<i16>, Ratio<i16>) = match n.region {
             Ray => (Ratio::new(1, 24), Ratio::new(3, 24)),
             _ => (Ratio::new(0, 12), Ratio::new(0, 12)),
         };
-        let hue_a = n.angle.add(ao).mul(HUE_MAX).to_integer() as i16;
-        let hue_b = n.angle.add(bo).mul(HUE_MAX).to_integer() as i16;
+        let hue_a = n.angle.add(ao).mul(HUE_STEPS).to_integer() as i16;
+        let hue_b = n.angle.add(bo).mul(HUE_STEPS).to_integer() as i16;
 
-        let a = HSV::new(self.offset + hue_a, self.saturation, 0x80);
-        let b = HSV::new(self.offset + hue_b, self.saturation, 0x80);
-        (a, b)
+        (self.color(hue_a), self.color(hue_b))
     }
     fn tick(&mut self) {
-        self.offset += self.speed;
+        // Kept within one turn, so it never overflows
+        let o = self.offset + self.speed;
+        self.offset = ((o % HUE_STEPS) + HUE_STEPS) % HUE_STEPS;
     }
     fn debug(&self) -> Vec<String<consts::U16>, consts::U8> {
         let mut rv = Vec::new();
@@ -57,26 +91,31 @@ impl Render for Rainbow {
         rv
     }
 
-    fn knob1(&mut self, dir: Direction) {
-        use Direction::*;
-        match dir {
-            CW => {
-                self.speed += 1;
-            }
-            CCW => {
-                self.speed -= 1;
-            }
+    fn params(&self) -> &'static [Param] {
+        &PARAMS
+    }
+    fn get(&self, param: usize) -> i16 {
+        match param {
+            0 => self.speed,
+            1 => self.saturation as i16,
+            2 => self.palette,
+            3 => self.hue_map as i16,
+            _ => 0,
         }
     }
-    fn knob2(&mut self, dir: Direction) {
-        use Direction::*;
-        match dir {
-            CW => {
-                self.saturation += 1;
+    fn set(&mut self, param: usize, value: i16) {
+        match param {
+            0 => self.speed = value,
+            1 => {
+                self.saturation = value as u8;
+                self.choose_colors();
             }
-            CCW => {
-                self.saturation -= 1;
+            2 => {
+                self.palette = value;
+                self.choose_colors();
             }
+            3 => self.hue_map = HueMap::numbered(value),
+            _ => {}
         }
     }
 }
@@ -1,15 +1,22 @@
 use core::fmt::Write;
 
 use heapless::{consts, String, Vec};
+use smart_leds::RGB8;
 
-use crate::hsv::{HSV, HUE_MAX};
-use crate::knob::Direction;
+use crate::hsv::{HueMap, HSV, HUE_STEPS};
 use crate::m6::{Node, Region, Render};
+use crate::palette::{self, Palette};
+use crate::param::Param;
 
 pub struct Zoom {
     hue: i16,
     speed: i16,
     step: i16,
+    // See `palette::numbered`
+    palette: i16,
+    // Its colors, kept so harmonies aren't built for every LED
+    colors: Option<Palette>,
+    hue_map: HueMap,
 }
 
 impl Zoom {
@@ -17,12 +24,37 @@ impl Zoom {
         let hue = 0;
         let speed = 20;
         let step = -64;
-        Self { hue, speed, step }
+        let palette = 0;
+        let colors = None;
+        let hue_map = HueMap::Spectrum;
+        Self {
+            hue,
+            speed,
+            step,
+            palette,
+            colors,
+            hue_map,
+        }
+    }
+    fn color(&self, steps: i16) -> RGB8 {
+        let c = HSV::new(self.hue + (steps * self.step), 0x60, 0x80);
+        match self.colors {
+            Some(p) => p.color(Palette::hue_index(c.h), c.v),
+            None => c.to_rgb_mapped(self.hue_map).into(),
+        }
     }
 }
 
+const PARAMS: [Param; 4] = [
+    Param::new("speed", -128, 128, 1),
+    Param::new("step", -512, 512, 1),
+    Param::wrapping("palette", 0, palette::CHOICES, 1),
+    // See `HueMap`, for when there's no palette
+    Param::wrapping("hue map", 0, 1, 1),
+];
+
 impl Render for Zoom {
-    fn render(&self, n: &Node) -> (HSV, HSV) {
+    fn render(&self, n: &Node) -> (RGB8, RGB8) {
         use Region::*;
         let (sa, sb): (i16, i16) = match n.region {
             Center => (0, 0),
@@ -31,13 +63,11 @@ impl Render for Zoom {
             Outer => (4, 6),
         };
 
-        let a = HSV::new(self.hue + (sa * self.step), 0x60, 0x80);
-        let b = HSV::new(self.hue + (sb * self.step), 0x60, 0x80);
-        (a, b)
+        (self.color(sa), self.color(sb))
     }
     fn tick(&mut self) {
         let h = self.hue + self.speed;
-        let h = ((h % HUE_MAX) + HUE_MAX) % HUE_MAX;
+        let h = ((h % HUE_STEPS) + HUE_STEPS) % HUE_STEPS;
         self.hue = h;
     }
     fn debug(&self) -> Vec<String<consts::U16>, consts::U8> {
@@ -54,26 +84,29 @@ impl Render for Zoom {
         rv
     }
 
-    fn knob1(&mut self, dir: Direction) {
-        use Direction::*;
-        match dir {
-            CW => {
-                self.speed += 1;
-            }
-            CCW => {
-                self.speed -= 1;
-            }
+    fn params(&self) -> &'static [Param] {
+        &PARAMS
+    }
+    fn get(&self, param: usize) -> i16 {
+        match param {
+            0 => self.speed,
+            1 => self.step,
+            2 => self.palette,
+            3 => self.hue_map as i16,
+            _ => 0,
         }
     }
-    fn knob2(&mut self, dir: Direction) {
-        use Direction::*;
-        match dir {
-            CW => {
-                self.step += 1;
-            }
-            CCW => {
-                self.step -= 1;
+    fn set(&mut self, param: usize, value: i16) {
+        match param {
+            0 => self.speed = value,
+            1 => self.step = value,
+            2 => {
+                self.palette = value;
+                // Harmonies start from red, which the hue turns away from
+                self.colors = palette::numbered(value, &HSV::new(0, 0xff, 0xff));
             }
+            3 => self.hue_map = HueMap::numbered(value),
+            _ => {}
         }
     }
 }
@@ -0,0 +1,23 @@
+use core::fmt;
+
+use embedded_hal::serial;
+
+// Blocking core::fmt::Write over a serial transmitter
+pub struct Writer<'a, W: serial::Write<u8>>(pub &'a mut W);
+
+impl<'a, W: serial::Write<u8>> fmt::Write for Writer<'a, W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for b in s.bytes() {
+            nb::block!(self.0.write(b)).map_err(|_| fmt::Error)?;
+        }
+        Ok(())
+    }
+}
+
+// Blocks until every byte is queued
+pub fn write_all<W: serial::Write<u8>>(w: &mut W, bytes: &[u8]) -> Result<(), W::Error> {
+    for &b in bytes {
+        nb::block!(w.write(b))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use smart_leds::RGB8;
+
+use crate::display::{Level, Screen};
+use crate::dmx::{self, Personality};
+use crate::midi;
+use crate::param::Param;
+
+// Frames per second of the tick task
+pub const FPS: u32 = 30;
+
+// APA102 draw: about 20mA per channel at full duty, 1mA idle per LED
+const CHANNEL_MA: u32 = 20;
+const IDLE_MA: u32 = 1;
+
+// How many settings there are
+pub const COUNT: usize = 8;
+
+const PARAMS: [Param; COUNT] = [
+    Param::new("brightness", 0, 255, 8),
+    // Milliamps for the whole strip
+    Param::new("power limit", 100, 4000, 100),
+    // Seconds per pattern, 0 to stay put
+    Param::new("auto cycle", 0, 600, 5),
+    // 0 for the debug lines, 1 for the LED preview
+    Param::wrapping("screen", 0, 1, 1),
+    // Seconds without input before the screen blanks, dimmed for the second
+    // half; 0 to keep it on
+    Param::new("sleep", 0, 3600, 30),
+    // First channel taken from a DMX universe, see `dmx`
+    Param::new("dmx address", 1, 512, 1),
+    // 0 for control channels, 1 for direct RGB
+    Param::wrapping("dmx mode", 0, 1, 1),
+    // What USART2 is wired to, see `Role`
+    Param::wrapping("port", 0, 2, 1),
+];
+
+// What USART2 is wired to; PA2 and PA3 serve one at a time
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    // The binary protocol, see glow-proto
+    Protocol = 0,
+    // DMX512 through an RS-485 transceiver, held receiving
+    Dmx = 1,
+    // A MIDI in port, through its optocoupler
+    Midi = 2,
+}
+
+impl Role {
+    pub fn baud(&self) -> u32 {
+        match self {
+            Role::Protocol => 115_200,
+            Role::Dmx => dmx::BAUD,
+            Role::Midi => midi::BAUD,
+        }
+    }
+}
+
+// Global settings, exposed through the same parameter API as the patterns
+#[derive(Clone, Copy, Debug)]
+pub struct Settings {
+    pub brightness: u8,
+    pub power_limit: u16,
+    pub auto_cycle: u16,
+    pub screen: Screen,
+    pub sleep: u16,
+    pub dmx_address: u16,
+    pub dmx_mode: Personality,
+    pub port: Role,
+}
+
+impl Settings {
+    pub const fn new() -> Self {
+        Self {
+            brightness: 255,
+            power_limit: 2000,
+            auto_cycle: 0,
+            screen: Screen::Debug,
+            sleep: 300,
+            dmx_address: 1,
+            dmx_mode: Personality::Control,
+            port: Role::Protocol,
+        }
+    }
+    pub fn params(&self) -> &'static [Param] {
+        &PARAMS
+    }
+    pub fn get(&self, param: usize) -> i16 {
+        match param {
+            0 => self.brightness as i16,
+            1 => self.power_limit as i16,
+            2 => self.auto_cycle as i16,
+            3 => self.screen as i16,
+            4 => self.sleep as i16,
+            5 => self.dmx_address as i16,
+            6 => self.dmx_mode as i16,
+            7 => self.port as i16,
+            _ => 0,
+        }
+    }
+    pub fn set(&mut self, param: usize, value: i16) {
+        let value = match PARAMS.get(param) {
+            Some(p) => p.clamp(value as i32),
+            None => return,
+        };
+        match param {
+            0 => self.brightness = value as u8,
+            1 => self.power_limit = value as u16,
+            2 => self.auto_cycle = value as u16,
+            3 => {
+                self.screen = match value {
+                    0 => Screen::Debug,
+                    _ => Screen::Preview,
+                }
+            }
+            4 => self.sleep = value as u16,
+            5 => self.dmx_address = value as u16,
+            6 => {
+                self.dmx_mode = match value {
+                    0 => Personality::Control,
+                    _ => Personality::Direct,
+                }
+            }
+            7 => {
+                self.port = match value {
+                    0 => Role::Protocol,
+                    1 => Role::Dmx,
+                    _ => Role::Midi,
+                }
+            }
+            _ => {}
+        }
+    }
+    pub fn adjust(&mut self, param: usize, delta: i16) {
+        if let Some(p) = PARAMS.get(param) {
+            self.set(param, p.adjusted(self.get(param), delta));
+        }
+    }
+    // Scale a frame by the brightness, then further if it would draw more
+    // than the power limit
+    pub fn limit(&self, leds: &mut [RGB8]) {
+        scale(leds, self.brightness as u32, 255);
+        let ma = estimate_ma(leds);
+        let limit = self.power_limit as u32;
+        if ma > limit {
+            let idle = leds.len() as u32 * IDLE_MA;
+            scale(leds, limit.saturating_sub(idle), ma - idle);
+        }
+    }
+    pub fn auto_cycle_frames(&self) -> u32 {
+        self.auto_cycle as u32 * FPS
+    }
+    pub fn screen_level(&self, idle_frames: u32) -> Level {
+        let sleep = self.sleep as u32 * FPS;
+        if sleep == 0 || idle_frames < sleep / 2 {
+            Level::Awake
+        } else if idle_frames < sleep {
+            Level::Dim
+        } else {
+            Level::Off
+        }
+    }
+}
+
+fn scale(leds: &mut [RGB8], num: u32, den: u32) {
+    if num >= den {
+        return;
+    }
+    let s = |x: u8| ((x as u32 * num) / den) as u8;
+    for c in leds.iter_mut() {
+        *c = RGB8 {
+            r: s(c.r),
+            g: s(c.g),
+            b: s(c.b),
+        };
+    }
+}
+
+pub fn estimate_ma(leds: &[RGB8]) -> u32 {
+    let duty: u32 = leds
+        .iter()
+        .map(|c| c.r as u32 + c.g as u32 + c.b as u32)
+        .sum();
+    (duty * CHANNEL_MA) / 255 + leds.len() as u32 * IDLE_MA
+}
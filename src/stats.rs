@@ -0,0 +1,96 @@
+use core::fmt::{self, Write};
+
+use heapless::{consts, String};
+
+use crate::display::{Canvas, ROWS};
+
+// Timing of the frames written to the strip, all in cycles of a clock
+// running at `hz`
+#[derive(Clone, Copy, Debug)]
+pub struct FrameStats {
+    hz: u32,
+    // Of the last frame
+    pub render: u32,
+    pub write: u32,
+    // Frames that ended after the next one was due
+    pub overruns: u32,
+    // Frames counted over the last full second
+    pub fps: u32,
+    window_start: u32,
+    window_frames: u32,
+}
+
+impl FrameStats {
+    pub const fn new(hz: u32) -> Self {
+        Self {
+            hz,
+            render: 0,
+            write: 0,
+            overruns: 0,
+            fps: 0,
+            window_start: 0,
+            window_frames: 0,
+        }
+    }
+    // `late` is how long after it was due the frame finished
+    pub fn frame(&mut self, now: u32, render: u32, write: u32, late: u32, period: u32) {
+        self.render = render;
+        self.write = write;
+        if late > period {
+            self.overruns = self.overruns.wrapping_add(1);
+        }
+        self.window_frames += 1;
+        if now.wrapping_sub(self.window_start) >= self.hz {
+            self.fps = self.window_frames;
+            self.window_frames = 0;
+            self.window_start = now;
+        }
+    }
+    pub fn micros(&self, cycles: u32) -> u32 {
+        cycles / (self.hz / 1_000_000).max(1)
+    }
+    // Frame rate, render time and estimated current on the bottom row, in
+    // the 16 columns left of the LED preview, e.g. "60f  812us 450mA"
+    pub fn draw(&self, c: &mut impl Canvas, ma: u32) {
+        let mut line: String<consts::U32> = String::new();
+        let _ = write!(
+            line,
+            "{:>2}f{:>5}us{:>4}mA",
+            self.fps,
+            self.micros(self.render),
+            ma
+        );
+        c.text(ROWS as u8 - 1, &line);
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} fps, {} late, render {} us, spi {} us",
+            self.fps,
+            self.overruns,
+            self.micros(self.render),
+            self.micros(self.write)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::TextBuffer;
+
+    #[test]
+    fn status_row_clears_the_preview() {
+        let mut s = FrameStats::new(24_000_000);
+        for i in 1..=60 {
+            s.frame(i * 400_000, 19_488, 9_600, 0, 800_000);
+        }
+        let mut t = TextBuffer::new();
+        s.draw(&mut t, 450);
+        assert_eq!(t.row(3), "60f  812us 450mA");
+        assert_eq!(t.row(0), "");
+    }
+}
@@ -0,0 +1,617 @@
+// Key/value records in flash, kept across power cycles. Two pages take
+// turns: records are appended to the active one, and when it fills, the
+// latest record of each key is copied to the other, which then takes over.
+//
+// Each page starts with a status half word, then records of
+//
+//   key: u16, len: u16, data padded to a half word, crc: u16
+//
+// little endian, with the CRC-16/CCITT-FALSE of everything before it. A
+// record cut short by a power loss fails its CRC, or leaves an unwritten
+// length that ends the page early, so the value before it still reads
+// back. A copy is only trusted once the old page has been erased and the
+// new one marked valid; until then the old page is used and the copy
+// started over.
+use glow_proto::crc::crc16;
+
+use crate::app::App;
+use crate::preset::{Preset, MAX_PARAMS, PRESETS};
+
+// Half words of flash, as the STM32F1 programs them; bits only go from 1 to
+// 0 until the page is erased
+pub trait FlashStorage {
+    type Error;
+    // Bytes per erasable page
+    fn page_size(&self) -> usize;
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), Self::Error>;
+    // Offset and length are even, and the half words erased
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), Self::Error>;
+    fn erase(&mut self, page: usize) -> Result<(), Self::Error>;
+}
+
+const ERASED: u16 = 0xffff;
+const RECEIVING: u16 = 0xeeee;
+const VALID: u16 = 0x0000;
+
+// Longest value
+pub const MAX_VALUE: usize = 64;
+// Key, length and CRC
+const OVERHEAD: usize = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error<E> {
+    Flash(E),
+    // Longer than MAX_VALUE, or the reserved key 0xffff
+    BadRecord,
+    // More distinct keys than one page holds
+    Full,
+}
+
+// Where a record's data is
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Record {
+    key: u16,
+    len: usize,
+    at: usize,
+}
+
+fn padded(len: usize) -> usize {
+    (len + 1) & !1
+}
+
+pub struct Store<F: FlashStorage> {
+    flash: F,
+    active: usize,
+    // Where the next record goes in the active page
+    end: usize,
+}
+
+impl<F: FlashStorage> Store<F> {
+    // Picks up the valid page, finishing or discarding a copy that a power
+    // loss interrupted; blank flash is formatted
+    pub fn open(flash: F) -> Result<Self, Error<F::Error>> {
+        let mut store = Self {
+            flash,
+            active: 0,
+            end: 2,
+        };
+        store.recover().map_err(Error::Flash)?;
+        Ok(store)
+    }
+    fn recover(&mut self) -> Result<(), F::Error> {
+        let statuses = (self.status(0)?, self.status(1)?);
+        self.active = match statuses {
+            // Along with an unfinished copy or anything else
+            (VALID, _) => {
+                self.clear(1)?;
+                0
+            }
+            (_, VALID) => {
+                self.clear(0)?;
+                1
+            }
+            // The old page was being erased, so the copy was complete
+            (RECEIVING, _) => {
+                self.clear(1)?;
+                self.mark(0, VALID)?;
+                0
+            }
+            (_, RECEIVING) => {
+                self.clear(0)?;
+                self.mark(1, VALID)?;
+                1
+            }
+            _ => {
+                self.clear(0)?;
+                self.clear(1)?;
+                self.mark(0, VALID)?;
+                0
+            }
+        };
+        self.end = self.scan(self.active, |_| {})?;
+        Ok(())
+    }
+    pub fn into_inner(self) -> F {
+        self.flash
+    }
+    // Copies the latest value into `buf`, returning its length
+    pub fn read(&mut self, key: u16, buf: &mut [u8]) -> Result<Option<usize>, Error<F::Error>> {
+        let r = match self.find(self.active, key).map_err(Error::Flash)? {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        let len = r.len.min(buf.len());
+        let at = self.base(self.active) + r.at;
+        self.flash.read(at, &mut buf[..len]).map_err(Error::Flash)?;
+        Ok(Some(r.len))
+    }
+    // Nothing is written when the value hasn't changed
+    pub fn write(&mut self, key: u16, data: &[u8]) -> Result<(), Error<F::Error>> {
+        if key == ERASED || data.len() > MAX_VALUE {
+            return Err(Error::BadRecord);
+        }
+        let mut old = [0; MAX_VALUE];
+        if self.read(key, &mut old)? == Some(data.len()) && &old[..data.len()] == data {
+            return Ok(());
+        }
+        let size = OVERHEAD + padded(data.len());
+        if self.end + size <= self.flash.page_size() {
+            let at = self.base(self.active) + self.end;
+            self.append(at, key, data).map_err(Error::Flash)?;
+            self.end += size;
+            return Ok(());
+        }
+        self.compact(key, data).map_err(|e| match e {
+            Some(e) => Error::Flash(e),
+            None => Error::Full,
+        })
+    }
+    fn base(&self, page: usize) -> usize {
+        page * self.flash.page_size()
+    }
+    fn status(&mut self, page: usize) -> Result<u16, F::Error> {
+        let mut b = [0; 2];
+        self.flash.read(self.base(page), &mut b)?;
+        Ok(u16::from_le_bytes(b))
+    }
+    fn mark(&mut self, page: usize, status: u16) -> Result<(), F::Error> {
+        let at = self.base(page);
+        self.flash.write(at, &status.to_le_bytes())
+    }
+    // Erases the page unless it already is
+    fn clear(&mut self, page: usize) -> Result<(), F::Error> {
+        let mut buf = [0; 16];
+        let mut at = 0;
+        while at < self.flash.page_size() {
+            let n = buf.len().min(self.flash.page_size() - at);
+            self.flash.read(self.base(page) + at, &mut buf[..n])?;
+            if buf[..n].iter().any(|&b| b != 0xff) {
+                return self.flash.erase(page);
+            }
+            at += n;
+        }
+        Ok(())
+    }
+    // Calls `f` with every record whose CRC checks, oldest first, and
+    // returns where the next one would go
+    fn scan(&mut self, page: usize, mut f: impl FnMut(Record)) -> Result<usize, F::Error> {
+        let base = self.base(page);
+        let size = self.flash.page_size();
+        let mut at = 2;
+        while at + OVERHEAD <= size {
+            let mut head = [0; 4];
+            self.flash.read(base + at, &mut head)?;
+            let key = u16::from_le_bytes([head[0], head[1]]);
+            let len = u16::from_le_bytes([head[2], head[3]]) as usize;
+            if key == ERASED {
+                break;
+            }
+            let next = at + OVERHEAD + padded(len);
+            // Cut short before its length was written; nothing after it
+            // can be trusted
+            if len > MAX_VALUE || next > size {
+                return Ok(size);
+            }
+            let mut buf = [0; 4 + MAX_VALUE + 2];
+            let n = 4 + padded(len) + 2;
+            self.flash.read(base + at, &mut buf[..n])?;
+            let crc = u16::from_le_bytes([buf[n - 2], buf[n - 1]]);
+            if crc16(&buf[..4 + len]) == crc {
+                f(Record {
+                    key,
+                    len,
+                    at: at + 4,
+                });
+            }
+            at = next;
+        }
+        Ok(at)
+    }
+    fn find(&mut self, page: usize, key: u16) -> Result<Option<Record>, F::Error> {
+        let mut found = None;
+        self.scan(page, |r| {
+            if r.key == key {
+                found = Some(r);
+            }
+        })?;
+        Ok(found)
+    }
+    fn append(&mut self, at: usize, key: u16, data: &[u8]) -> Result<(), F::Error> {
+        let mut buf = [0xff; 4 + MAX_VALUE + 2];
+        buf[..2].copy_from_slice(&key.to_le_bytes());
+        buf[2..4].copy_from_slice(&(data.len() as u16).to_le_bytes());
+        buf[4..4 + data.len()].copy_from_slice(data);
+        let n = 4 + padded(data.len());
+        let crc = crc16(&buf[..4 + data.len()]);
+        buf[n..n + 2].copy_from_slice(&crc.to_le_bytes());
+        // The CRC last, so the record only checks once it is all there
+        self.flash.write(at, &buf[..n])?;
+        self.flash.write(at + n, &buf[n..n + 2])
+    }
+    // Writes the new value, then the latest of every other key, to the
+    // other page; None if they don't fit
+    fn compact(&mut self, key: u16, data: &[u8]) -> Result<(), Option<F::Error>> {
+        let (from, to) = (self.active, 1 - self.active);
+        let size = self.flash.page_size();
+        self.clear(to).map_err(Some)?;
+        self.mark(to, RECEIVING).map_err(Some)?;
+        let mut end = 2;
+        self.append(self.base(to) + end, key, data).map_err(Some)?;
+        end += OVERHEAD + padded(data.len());
+        // Keys are copied in the order they were last written
+        let mut at = 2;
+        loop {
+            let mut next = None;
+            self.scan(from, |r| {
+                if r.at > at && next.map_or(true, |n: Record| r.at < n.at) {
+                    next = Some(r);
+                }
+            })
+            .map_err(Some)?;
+            let r = match next {
+                Some(r) => r,
+                None => break,
+            };
+            at = r.at;
+            let latest = self.find(from, r.key).map_err(Some)?;
+            if r.key == key || latest != Some(r) {
+                continue;
+            }
+            if end + OVERHEAD + padded(r.len) > size {
+                return Err(None);
+            }
+            let mut buf = [0; MAX_VALUE];
+            self.flash
+                .read(self.base(from) + r.at, &mut buf[..r.len])
+                .map_err(Some)?;
+            self.append(self.base(to) + end, r.key, &buf[..r.len])
+                .map_err(Some)?;
+            end += OVERHEAD + padded(r.len);
+        }
+        self.flash.erase(from).map_err(Some)?;
+        self.mark(to, VALID).map_err(Some)?;
+        self.active = to;
+        self.end = end;
+        Ok(())
+    }
+}
+
+// What is kept: the settings, the pattern showing and its parameters, and
+// the presets
+const SETTINGS: u16 = 1;
+const CURRENT: u16 = 2;
+const PRESET: u16 = 0x10;
+
+fn preset_bytes(p: &Preset, buf: &mut [u8; 2 + 2 * MAX_PARAMS]) {
+    buf[0] = p.pattern;
+    buf[1] = 0;
+    for (b, v) in buf[2..].chunks_mut(2).zip(p.values.iter()) {
+        b.copy_from_slice(&v.to_le_bytes());
+    }
+}
+
+fn preset_from(buf: &[u8]) -> Option<Preset> {
+    if buf.len() != 2 + 2 * MAX_PARAMS {
+        return None;
+    }
+    let mut values = [0; MAX_PARAMS];
+    for (v, b) in values.iter_mut().zip(buf[2..].chunks(2)) {
+        *v = i16::from_le_bytes([b[0], b[1]]);
+    }
+    let pattern = buf[0];
+    Some(Preset { pattern, values })
+}
+
+// Only what changed is written
+pub fn save<F: FlashStorage>(store: &mut Store<F>, app: &App) -> Result<(), Error<F::Error>> {
+    let settings = &app.settings;
+    let mut buf = [0; MAX_VALUE];
+    let n = settings.params().len();
+    for i in 0..n {
+        buf[2 * i..2 * i + 2].copy_from_slice(&settings.get(i).to_le_bytes());
+    }
+    store.write(SETTINGS, &buf[..2 * n])?;
+    let mut buf = [0; 2 + 2 * MAX_PARAMS];
+    preset_bytes(&Preset::capture(&app.patterns), &mut buf);
+    store.write(CURRENT, &buf)?;
+    for slot in 0..PRESETS as u8 {
+        if let Some(p) = app.preset(slot) {
+            preset_bytes(&p, &mut buf);
+            store.write(PRESET + slot as u16, &buf)?;
+        }
+    }
+    Ok(())
+}
+
+// Settings saved by older firmware keep the defaults of any added since,
+// and values are clamped to their current ranges
+pub fn load<F: FlashStorage>(store: &mut Store<F>, app: &mut App) -> Result<(), Error<F::Error>> {
+    let mut buf = [0; MAX_VALUE];
+    if let Some(n) = store.read(SETTINGS, &mut buf)? {
+        let n = n.min(MAX_VALUE) / 2;
+        for i in 0..n.min(app.settings.params().len()) {
+            let v = i16::from_le_bytes([buf[2 * i], buf[2 * i + 1]]);
+            app.settings.set(i, v);
+        }
+    }
+    for slot in 0..PRESETS as u8 {
+        if let Some(n) = store.read(PRESET + slot as u16, &mut buf)? {
+            if let Some(p) = preset_from(&buf[..n.min(MAX_VALUE)]) {
+                app.set_preset(slot, p);
+            }
+        }
+    }
+    if let Some(n) = store.read(CURRENT, &mut buf)? {
+        if let Some(p) = preset_from(&buf[..n.min(MAX_VALUE)]) {
+            p.apply(&mut app.patterns);
+        }
+    }
+    Ok(())
+}
+
+// Flash in RAM, for the host: programming only clears bits, like the real
+// thing, and a power loss can be simulated
+pub struct MemoryFlash<'a> {
+    data: &'a mut [u8],
+    page_size: usize,
+    // Half words that can still be written before the power goes
+    power: Option<usize>,
+    // Of each page
+    pub erases: [u32; 2],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemoryError {
+    OutOfRange,
+    Unaligned,
+    NotErased,
+    PowerLost,
+}
+
+impl<'a> MemoryFlash<'a> {
+    // Two pages of `data`, starting out erased
+    pub fn new(data: &'a mut [u8]) -> Self {
+        for b in data.iter_mut() {
+            *b = 0xff;
+        }
+        let page_size = data.len() / 2;
+        Self {
+            data,
+            page_size,
+            power: None,
+            erases: [0; 2],
+        }
+    }
+    // Keeps what was there, e.g. after a simulated power loss
+    pub fn reopen(data: &'a mut [u8]) -> Self {
+        let page_size = data.len() / 2;
+        Self {
+            data,
+            page_size,
+            power: None,
+            erases: [0; 2],
+        }
+    }
+    // Fails every write and erase after `half_words` more half words
+    pub fn cut_power_after(&mut self, half_words: usize) {
+        self.power = Some(half_words);
+    }
+}
+
+impl<'a> FlashStorage for MemoryFlash<'a> {
+    type Error = MemoryError;
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+    fn read(&mut self, offset: usize, buf: &mut [u8]) -> Result<(), MemoryError> {
+        let src = self
+            .data
+            .get(offset..offset + buf.len())
+            .ok_or(MemoryError::OutOfRange)?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+    fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), MemoryError> {
+        if offset % 2 != 0 || data.len() % 2 != 0 {
+            return Err(MemoryError::Unaligned);
+        }
+        if offset + data.len() > self.data.len() {
+            return Err(MemoryError::OutOfRange);
+        }
+        for (i, hw) in data.chunks(2).enumerate() {
+            let at = offset + 2 * i;
+            match &mut self.power {
+                Some(0) => return Err(MemoryError::PowerLost),
+                Some(n) => *n -= 1,
+                None => {}
+            }
+            // Zero can be written over anything, which marks pages valid
+            if self.data[at..at + 2] != [0xff, 0xff] && hw != [0, 0] {
+                return Err(MemoryError::NotErased);
+            }
+            self.data[at..at + 2].copy_from_slice(hw);
+        }
+        Ok(())
+    }
+    fn erase(&mut self, page: usize) -> Result<(), MemoryError> {
+        if self.power == Some(0) {
+            return Err(MemoryError::PowerLost);
+        }
+        let at = page * self.page_size;
+        let p = self
+            .data
+            .get_mut(at..at + self.page_size)
+            .ok_or(MemoryError::OutOfRange)?;
+        for b in p.iter_mut() {
+            *b = 0xff;
+        }
+        self.erases[page] += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Action, InputEvent, Mapping};
+
+    // Two pages of 128 bytes, so they fill quickly
+    const SIZE: usize = 256;
+
+    fn value(s: &mut Store<MemoryFlash>, key: u16) -> Option<u32> {
+        let mut buf = [0; 4];
+        let n = s.read(key, &mut buf).unwrap()?;
+        assert_eq!(n, 4);
+        Some(u32::from_le_bytes(buf))
+    }
+
+    // Cuts the power after every number of half words that writing `new`
+    // to key 1 takes, and checks that the old value or the new one reads
+    // back while key 2 keeps its own; returns the half words it took
+    fn cut_everywhere(mem: &[u8; SIZE], new: u32) -> usize {
+        let mut m = *mem;
+        let mut s = Store::open(MemoryFlash::reopen(&mut m)).unwrap();
+        let (old, other) = (value(&mut s, 1), value(&mut s, 2));
+        for cut in 0.. {
+            let mut m = *mem;
+            let mut f = MemoryFlash::reopen(&mut m);
+            f.cut_power_after(cut);
+            let mut s = Store::open(f).unwrap();
+            let done = s.write(1, &new.to_le_bytes()).is_ok();
+            let mut s = Store::open(MemoryFlash::reopen(&mut m)).unwrap();
+            let v = value(&mut s, 1);
+            if done {
+                assert_eq!(v, Some(new));
+            } else {
+                assert!(v == old || v == Some(new), "cut {}: {:?}", cut, v);
+            }
+            assert_eq!(value(&mut s, 2), other, "cut {}", cut);
+            // Still usable
+            s.write(1, &(new + 1).to_le_bytes()).unwrap();
+            assert_eq!(value(&mut s, 1), Some(new + 1));
+            if done {
+                return cut;
+            }
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn power_loss_while_appending() {
+        let mut mem = [0; SIZE];
+        let mut s = Store::open(MemoryFlash::new(&mut mem)).unwrap();
+        s.write(1, &7u32.to_le_bytes()).unwrap();
+        s.write(2, &9u32.to_le_bytes()).unwrap();
+        // Key, length, value and CRC
+        assert_eq!(cut_everywhere(&mem, 8), 5);
+    }
+
+    #[test]
+    fn power_loss_while_compacting() {
+        let mut mem = [0; SIZE];
+        let mut s = Store::open(MemoryFlash::new(&mut mem)).unwrap();
+        s.write(2, &9u32.to_le_bytes()).unwrap();
+        s.write(3, b"constant").unwrap();
+        let mut i = 0u32;
+        // From each page to the other
+        for _ in 0..2 {
+            while s.end + OVERHEAD + 4 <= SIZE / 2 {
+                i += 1;
+                s.write(1, &i.to_le_bytes()).unwrap();
+            }
+            let mut copy = [0; SIZE];
+            s.flash.read(0, &mut copy).unwrap();
+            // The status, three records and another status
+            let took = cut_everywhere(&copy, i + 1);
+            assert_eq!(took, 1 + 5 + 5 + 7 + 1);
+            let active = s.active;
+            i += 1;
+            s.write(1, &i.to_le_bytes()).unwrap();
+            assert_ne!(s.active, active);
+        }
+    }
+
+    #[test]
+    fn wear_is_spread() {
+        let mut mem = [0; 2048];
+        let mut s = Store::open(MemoryFlash::new(&mut mem)).unwrap();
+        let mut buf = [0; MAX_VALUE];
+        assert_eq!(s.read(1, &mut buf).unwrap(), None);
+        for i in 0..1000u32 {
+            s.write(1, &i.to_le_bytes()).unwrap();
+            s.write(2, &[i as u8; 3]).unwrap();
+            s.write(3, b"constant").unwrap();
+            let [a, b] = s.flash.erases;
+            assert!(a == b || a == b + 1, "{} {}", a, b);
+        }
+        // 2000 records of 10 to 12 bytes in 1K pages
+        let erases = s.flash.erases[0] + s.flash.erases[1];
+        assert!(erases > 10 && erases < 40, "{}", erases);
+        let mut s = Store::open(MemoryFlash::reopen(&mut mem)).unwrap();
+        assert_eq!(s.read(1, &mut buf).unwrap(), Some(4));
+        assert_eq!(buf[..4], 999u32.to_le_bytes());
+        assert_eq!(s.read(2, &mut buf).unwrap(), Some(3));
+        assert_eq!(buf[..3], [231; 3]);
+        assert_eq!(s.read(3, &mut buf).unwrap(), Some(8));
+    }
+
+    #[test]
+    fn bad_and_too_many_keys() {
+        let mut mem = [0; SIZE];
+        let mut s = Store::open(MemoryFlash::new(&mut mem)).unwrap();
+        assert_eq!(s.write(ERASED, b"x"), Err(Error::BadRecord));
+        assert_eq!(s.write(1, &[0; MAX_VALUE + 1]), Err(Error::BadRecord));
+        // Nine records of 14 bytes fill a page
+        for k in 0..9 {
+            s.write(k, &[k as u8; 8]).unwrap();
+        }
+        assert_eq!(s.write(9, &[9; 8]), Err(Error::Full));
+        let mut buf = [0; 8];
+        assert_eq!(s.read(9, &mut buf).unwrap(), None);
+        // The keys there are can still change, before and after a reopen
+        s.write(0, &[10; 8]).unwrap();
+        let mut s = Store::open(MemoryFlash::reopen(&mut mem)).unwrap();
+        for k in 0..9 {
+            assert_eq!(s.read(k, &mut buf).unwrap(), Some(8));
+            assert_eq!(buf, [if k == 0 { 10 } else { k as u8 }; 8]);
+        }
+        s.write(8, &[0; 8]).unwrap();
+    }
+
+    #[test]
+    fn saves_and_loads_the_app() {
+        let mut mem = [0; 2048];
+        let mut app = App::new(Mapping::new());
+        let actions = [
+            Action::Select(2),
+            Action::Set { param: 0, value: 7 },
+            Action::Save(1),
+            Action::Select(1),
+            Action::Setting {
+                param: 0,
+                value: 100,
+            },
+            Action::Setting {
+                param: 5,
+                value: 33,
+            },
+        ];
+        for &a in &actions {
+            app.input(InputEvent::Command(a));
+        }
+        let mut s = Store::open(MemoryFlash::new(&mut mem)).unwrap();
+        save(&mut s, &app).unwrap();
+        let before = mem;
+        let mut s = Store::open(MemoryFlash::reopen(&mut mem)).unwrap();
+        let mut b = App::new(Mapping::new());
+        load(&mut s, &mut b).unwrap();
+        assert_eq!(b.patterns.active(), 1);
+        assert_eq!(b.settings.brightness, 100);
+        assert_eq!(b.settings.dmx_address, 33);
+        assert_eq!(b.preset(1), app.preset(1));
+        assert_eq!(b.preset(0), None);
+        // Nothing changed, so nothing is written
+        save(&mut s, &b).unwrap();
+        assert_eq!(mem[..], before[..]);
+    }
+}
@@ -0,0 +1,328 @@
+// Frames of raw LED colors from PC software such as Prismatik, Hyperion or
+// Jinx, picked out of the console's byte stream. Two framings are known:
+//
+//   Adalight: "Ada", count - 1 as u16 big endian, hi ^ lo ^ 0x55, RGB...
+//   TPM2:     0xc9, type, size as u16 big endian, data..., 0x36
+//
+// Only TPM2 data frames (type 0xda) are shown; its other packets are
+// skipped. Frames for more LEDs than the fixture has are cut short and
+// frames for fewer are padded with black. Neither framing can tell a
+// lost byte from data, so a pause mid frame starts over, see `push_at`.
+use crate::m6::LEDS;
+
+const ADA: &[u8] = b"Ada";
+const TPM2_START: u8 = 0xc9;
+const TPM2_DATA: u8 = 0xda;
+const TPM2_END: u8 = 0x36;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    Adalight,
+    Tpm2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    // Adalight header checksum
+    Checksum,
+    // A TPM2 packet not followed by its end byte
+    End,
+}
+
+// Bytes that turned out not to be a stream header, in order
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Text {
+    bytes: [u8; 3],
+    len: usize,
+}
+
+impl Text {
+    fn new(held: &[u8], b: Option<u8>) -> Self {
+        let mut bytes = [0; 3];
+        bytes[..held.len()].copy_from_slice(held);
+        let mut len = held.len();
+        if let Some(b) = b {
+            bytes[len] = b;
+            len += 1;
+        }
+        Self { bytes, len }
+    }
+    pub fn as_slice(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feed {
+    // For the console
+    Text(Text),
+    // Taken as part of a stream
+    Pending,
+    // Complete, see `StreamReader::frame`
+    Frame(Protocol),
+    Error(Error),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Idle,
+    // Matched this much of "Ada"
+    Magic(usize),
+    Header(Protocol),
+    // Payload bytes still to come; TPM2 packets that aren't shown are
+    // still read to the end
+    Data { left: u32, shown: bool },
+    End { shown: bool },
+}
+
+pub struct StreamReader {
+    state: State,
+    header: [u8; 3],
+    got: usize,
+    frame: [u8; LEDS * 3],
+    pos: usize,
+    protocol: Protocol,
+    // Longest pause within a frame, and when the last byte came
+    gap: u32,
+    last: u32,
+}
+
+impl StreamReader {
+    pub const fn new() -> Self {
+        Self::with_gap(u32::max_value())
+    }
+    pub const fn with_gap(gap: u32) -> Self {
+        Self {
+            state: State::Idle,
+            header: [0; 3],
+            got: 0,
+            frame: [0; LEDS * 3],
+            pos: 0,
+            protocol: Protocol::Adalight,
+            gap,
+            last: 0,
+        }
+    }
+    // RGB bytes for every LED, complete when `push` returns a frame and
+    // overwritten by the next one
+    pub fn frame(&self) -> &[u8] {
+        &self.frame
+    }
+    // As `push`, but after a pause of more than `gap` ticks any partial
+    // frame is dropped and `b` starts afresh, so a sender that stopped
+    // midway doesn't swallow what comes next; `now` is any free running
+    // tick counter
+    pub fn push_at(&mut self, b: u8, now: u32) -> Feed {
+        let paused = now.wrapping_sub(self.last) > self.gap;
+        self.last = now;
+        if paused {
+            match self.state {
+                State::Idle => {}
+                // Someone typing, most likely
+                State::Magic(n) => return self.not_magic(n, b),
+                _ => self.state = State::Idle,
+            }
+        }
+        self.push(b)
+    }
+    pub fn push(&mut self, b: u8) -> Feed {
+        match self.state {
+            State::Idle => match b {
+                b'A' => {
+                    self.state = State::Magic(1);
+                    Feed::Pending
+                }
+                TPM2_START => self.header(Protocol::Tpm2),
+                _ => Feed::Text(Text::new(&[], Some(b))),
+            },
+            State::Magic(n) if b == ADA[n] => {
+                if n + 1 == ADA.len() {
+                    self.header(Protocol::Adalight)
+                } else {
+                    self.state = State::Magic(n + 1);
+                    Feed::Pending
+                }
+            }
+            State::Magic(n) => self.not_magic(n, b),
+            State::Header(protocol) => {
+                self.header[self.got] = b;
+                self.got += 1;
+                if self.got < self.header.len() {
+                    return Feed::Pending;
+                }
+                self.start(protocol)
+            }
+            State::Data { left, shown } => {
+                if shown {
+                    if let Some(slot) = self.frame.get_mut(self.pos) {
+                        *slot = b;
+                    }
+                    self.pos += 1;
+                }
+                self.data(left - 1, shown)
+            }
+            State::End { shown } => {
+                self.state = State::Idle;
+                match (b == TPM2_END, shown) {
+                    (false, _) => Feed::Error(Error::End),
+                    (true, true) => Feed::Frame(Protocol::Tpm2),
+                    (true, false) => Feed::Pending,
+                }
+            }
+        }
+    }
+    // Not a header after all, though `b` may start one
+    fn not_magic(&mut self, n: usize, b: u8) -> Feed {
+        self.state = State::Idle;
+        match self.push(b) {
+            Feed::Text(t) => Feed::Text(Text::new(&ADA[..n], t.as_slice().first().cloned())),
+            _ => Feed::Text(Text::new(&ADA[..n], None)),
+        }
+    }
+    fn header(&mut self, protocol: Protocol) -> Feed {
+        self.state = State::Header(protocol);
+        self.got = 0;
+        Feed::Pending
+    }
+    fn start(&mut self, protocol: Protocol) -> Feed {
+        let [a, b, c] = self.header;
+        self.protocol = protocol;
+        let (left, shown) = match protocol {
+            Protocol::Adalight => {
+                if a ^ b ^ 0x55 != c {
+                    self.state = State::Idle;
+                    return Feed::Error(Error::Checksum);
+                }
+                ((u16::from_be_bytes([a, b]) as u32 + 1) * 3, true)
+            }
+            Protocol::Tpm2 => (u16::from_be_bytes([b, c]) as u32, a == TPM2_DATA),
+        };
+        if shown {
+            // Black for any LEDs the frame leaves out
+            self.frame = [0; LEDS * 3];
+            self.pos = 0;
+        }
+        self.data(left, shown)
+    }
+    fn data(&mut self, left: u32, shown: bool) -> Feed {
+        self.state = match (left, self.protocol) {
+            (0, Protocol::Adalight) => {
+                self.state = State::Idle;
+                return Feed::Frame(Protocol::Adalight);
+            }
+            (0, Protocol::Tpm2) => State::End { shown },
+            _ => State::Data { left, shown },
+        };
+        Feed::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::vec::Vec;
+
+    // Adalight from Prismatik for 2 LEDs, red then blue
+    const ADALIGHT: [u8; 12] = [
+        0x41, 0x64, 0x61, 0x00, 0x01, 0x54, 0xff, 0x00, 0x00, 0x00, 0x00, 0xff,
+    ];
+    // TPM2 from Jinx for 2 LEDs, green then white
+    const TPM2: [u8; 11] = [
+        0xc9, 0xda, 0x00, 0x06, 0x00, 0xff, 0x00, 0xff, 0xff, 0xff, 0x36,
+    ];
+    // A TPM2 command packet, which isn't shown
+    const TPM2_COMMAND: [u8; 7] = [0xc9, 0xc0, 0x00, 0x02, 0x0a, 0x01, 0x36];
+
+    // The console text and everything else that came out
+    fn feed(r: &mut StreamReader, bytes: &[u8]) -> (Vec<u8>, Vec<Feed>) {
+        let (mut text, mut rest) = (Vec::new(), Vec::new());
+        for &b in bytes {
+            match r.push(b) {
+                Feed::Text(t) => text.extend_from_slice(t.as_slice()),
+                Feed::Pending => {}
+                f => rest.push(f),
+            }
+        }
+        (text, rest)
+    }
+
+    #[test]
+    fn adalight_among_text() {
+        let mut r = StreamReader::new();
+        let bytes = [&b"help\r"[..], &ADALIGHT, b"AAdx\r"].concat();
+        let (text, rest) = feed(&mut r, &bytes);
+        assert_eq!(text, b"help\rAAdx\r");
+        assert_eq!(rest, [Feed::Frame(Protocol::Adalight)]);
+        assert_eq!(r.frame()[..6], [0xff, 0, 0, 0, 0, 0xff]);
+        // The LEDs the frame leaves out are black
+        assert!(r.frame()[6..].iter().all(|&b| b == 0));
+        let mut bad = ADALIGHT;
+        bad[5] ^= 1;
+        let (text, rest) = feed(&mut r, &bad[..6]);
+        assert_eq!(rest, [Feed::Error(Error::Checksum)]);
+        assert!(text.is_empty());
+    }
+
+    #[test]
+    fn long_adalight_frames_are_cut() {
+        let mut r = StreamReader::new();
+        let n = LEDS + 2;
+        let [hi, lo] = ((n - 1) as u16).to_be_bytes();
+        let mut bytes = [&b"Ada"[..], &[hi, lo, hi ^ lo ^ 0x55]].concat();
+        bytes.extend((0..n * 3).map(|i| i as u8));
+        bytes.extend_from_slice(b"ok");
+        let (text, rest) = feed(&mut r, &bytes);
+        assert_eq!(rest, [Feed::Frame(Protocol::Adalight)]);
+        assert_eq!(text, b"ok");
+        assert_eq!(r.frame()[LEDS * 3 - 1], (LEDS * 3 - 1) as u8);
+    }
+
+    #[test]
+    fn tpm2_data_and_commands() {
+        let mut r = StreamReader::new();
+        let bytes = [&TPM2_COMMAND[..], &TPM2, b"x"].concat();
+        let (text, rest) = feed(&mut r, &bytes);
+        assert_eq!(rest, [Feed::Frame(Protocol::Tpm2)]);
+        assert_eq!(text, b"x");
+        assert_eq!(r.frame()[..6], [0, 0xff, 0, 0xff, 0xff, 0xff]);
+        let mut bad = TPM2;
+        bad[10] = 0;
+        let (_, rest) = feed(&mut r, &bad);
+        assert_eq!(rest, [Feed::Error(Error::End)]);
+    }
+
+    #[test]
+    fn pauses_resync() {
+        let mut r = StreamReader::with_gap(100);
+        // Half a frame, then a pause and a whole one
+        let mut now = 1000;
+        for &b in &TPM2[..5] {
+            assert_eq!(r.push_at(b, now), Feed::Pending);
+            now += 10;
+        }
+        now += 500;
+        let mut rest = Vec::new();
+        for &b in &TPM2 {
+            match r.push_at(b, now) {
+                Feed::Pending => {}
+                f => rest.push(f),
+            }
+            now += 10;
+        }
+        assert_eq!(rest, [Feed::Frame(Protocol::Tpm2)]);
+        // Typed slowly, "Ada" is only text, even where the counter wraps
+        let mut text = Vec::new();
+        let mut now = u32::max_value() - 150;
+        for &b in b"Ada 1" {
+            match r.push_at(b, now) {
+                Feed::Text(t) => text.extend_from_slice(t.as_slice()),
+                Feed::Pending => {}
+                f => panic!("{:?}", f),
+            }
+            now = now.wrapping_add(200);
+        }
+        assert_eq!(text, b"Ada 1");
+    }
+}
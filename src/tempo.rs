@@ -0,0 +1,118 @@
+use crate::settings::FPS;
+
+// MIDI clock pulses per quarter note
+const PPQN: u32 = 24;
+// Frames without a pulse before the patterns run free again
+const TIMEOUT: u32 = FPS / 2;
+// Most pattern steps in one frame, however fast the clock
+const MAX_STEPS: u32 = 8;
+
+// Steps the patterns in time with an external clock instead of once a
+// frame: a beat is a second's worth of steps, so they run at their usual
+// speed at 60 BPM and twice that at 120
+pub struct Tempo {
+    // Steps owed, in PPQN-ths
+    owed: u32,
+    left: u32,
+    running: bool,
+}
+
+impl Tempo {
+    pub const fn new() -> Self {
+        Self {
+            owed: 0,
+            left: 0,
+            running: true,
+        }
+    }
+    pub fn pulse(&mut self) {
+        self.left = TIMEOUT;
+        if self.running {
+            self.owed = (self.owed + FPS).min(MAX_STEPS * PPQN);
+        }
+    }
+    // From the top of the beat
+    pub fn start(&mut self) {
+        self.owed = 0;
+        self.running = true;
+    }
+    pub fn resume(&mut self) {
+        self.running = true;
+    }
+    // Holds the patterns still until the next start or continue, whether
+    // the clock keeps going or not
+    pub fn stop(&mut self) {
+        self.running = false;
+    }
+    pub fn is_synced(&self) -> bool {
+        self.left > 0
+    }
+    // Pattern steps for this frame
+    pub fn steps(&mut self) -> u32 {
+        let synced = self.left > 0;
+        self.left = self.left.saturating_sub(1);
+        if !self.running {
+            return 0;
+        }
+        if !synced {
+            return 1;
+        }
+        let steps = self.owed / PPQN;
+        self.owed %= PPQN;
+        steps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Frames of clock at `bpm`, returning the steps taken
+    fn run(t: &mut Tempo, bpm: u32, frames: u32) -> u32 {
+        let (mut steps, mut acc) = (0, 0);
+        for _ in 0..frames {
+            acc += bpm * PPQN / 60;
+            while acc >= FPS {
+                acc -= FPS;
+                t.pulse();
+            }
+            steps += t.steps();
+        }
+        steps
+    }
+
+    #[test]
+    fn follows_the_clock() {
+        let mut t = Tempo::new();
+        assert_eq!(t.steps(), 1);
+        assert!(!t.is_synced());
+        // A second's worth of steps per beat, two beats a second
+        let steps = run(&mut t, 120, FPS);
+        assert!(steps >= 2 * FPS - 2 && steps <= 2 * FPS, "{}", steps);
+        assert!(t.is_synced());
+        // Running free once the clock goes away
+        for _ in 0..TIMEOUT {
+            t.steps();
+        }
+        assert!(!t.is_synced());
+        assert_eq!(t.steps(), 1);
+    }
+
+    #[test]
+    fn stop_holds_with_or_without_the_clock() {
+        let mut t = Tempo::new();
+        run(&mut t, 120, 5);
+        t.stop();
+        assert_eq!(run(&mut t, 120, FPS), 0);
+        // The clock stops too
+        for _ in 0..2 * TIMEOUT {
+            assert_eq!(t.steps(), 0);
+        }
+        assert!(!t.is_synced());
+        t.resume();
+        assert_eq!(t.steps(), 1);
+        t.stop();
+        t.start();
+        assert!(run(&mut t, 60, FPS) > 0);
+    }
+}